@@ -0,0 +1,25 @@
+use rrmvcc::db::*;
+use rrmvcc::resp::*;
+use std::{env, net::TcpListener, process};
+
+/// `rrmvcc-server [<address>] [<wal path>]`: serves the store over RESP, on
+/// 127.0.0.1:6379 unless told otherwise, in memory unless a write-ahead log is
+/// given. Transactions run at Snapshot unless `BEGIN ISOLATION` says otherwise.
+fn main() {
+    let mut args = env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:6379".to_string());
+    let mut db = match args.next() {
+        Some(path) => Database::open(&path).unwrap_or_else(|e| {
+            eprintln!("cannot open {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => Database::new(),
+    };
+    db.default_isolation_level = IsolationLevel::Snapshot;
+    let listener = TcpListener::bind(&addr).unwrap_or_else(|e| {
+        eprintln!("cannot listen on {}: {}", addr, e);
+        process::exit(1);
+    });
+    println!("listening on {}", addr);
+    serve(listener, db);
+}
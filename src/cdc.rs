@@ -0,0 +1,223 @@
+use crate::db::*;
+use crate::error::*;
+use crate::tx::*;
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+};
+
+/// How many events a subscriber may fall behind by. Commits never wait for
+/// subscribers: one whose receiver holds this many unread events when another
+/// commit comes misses it and every later one. It gets a last
+/// [`Error::ChangesLagged`] with the first commit after it read some of them,
+/// then its receiver ends.
+pub const CHANGE_BUFFER: usize = 1024;
+
+/// What a subscriber receives: change events in commit order, and an error if
+/// it fell behind.
+pub type Changes<K = KeyType, V = ValueType> = Receiver<Result<ChangeEvent<K, V>, Error<K>>>;
+
+/// What a committed transaction did to one key.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Change<K = KeyType, V = ValueType> {
+    /// `old` is the value before the commit, if the key had one.
+    Put {
+        key: K,
+        old: Option<V>,
+        new: V,
+    },
+    Delete {
+        key: K,
+        old: V,
+    },
+}
+
+/// The writes of a committed transaction, in key order.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ChangeEvent<K = KeyType, V = ValueType> {
+    pub tx_id: TxIdType,
+    /// The commit timestamp of the transaction, which is also the position of
+    /// the event in the stream.
+    pub commit_ts: u64,
+    pub changes: Vec<Change<K, V>>,
+}
+
+/// The change events kept for new subscribers, and where to send new ones.
+pub(crate) struct ChangeFeed<K, V> {
+    events: VecDeque<ChangeEvent<K, V>>,
+    /// Every event from this position on is still in `events`.
+    horizon: u64,
+    /// How many of the latest events are kept for
+    /// [`Database::subscribe_from`].
+    retention: usize,
+    subscribers: Vec<Subscriber<K, V>>,
+}
+
+impl<K, V> Default for ChangeFeed<K, V> {
+    fn default() -> Self {
+        ChangeFeed {
+            events: Default::default(),
+            horizon: 1,
+            retention: 0,
+            subscribers: Default::default(),
+        }
+    }
+}
+
+struct Subscriber<K, V> {
+    sender: SyncSender<Result<ChangeEvent<K, V>, Error<K>>>,
+    /// The first event it missed because its buffer was full.
+    lagged: Option<u64>,
+}
+
+impl<K: Clone, V: Clone> Subscriber<K, V> {
+    /// Sends `event`, or the error telling a lagging subscriber where it
+    /// fell behind. False once the subscriber is gone or was told.
+    fn send(&mut self, event: &ChangeEvent<K, V>) -> bool {
+        if self.lagged.is_none() {
+            match self.sender.try_send(Ok(event.clone())) {
+                Ok(()) => return true,
+                Err(TrySendError::Full(_)) => self.lagged = Some(event.commit_ts),
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        let from = self.lagged.unwrap();
+        matches!(
+            self.sender.try_send(Err(Error::ChangesLagged { from })),
+            Err(TrySendError::Full(_))
+        )
+    }
+}
+
+impl<K, V> ChangeFeed<K, V> {
+    /// Makes commits up to `commit_ts` unavailable, for a database whose
+    /// earlier commits were replayed from its log.
+    pub(crate) fn start_after(&mut self, commit_ts: u64) {
+        self.events.clear();
+        self.horizon = commit_ts + 1;
+    }
+
+    /// Forgets the oldest events beyond the retention.
+    fn trim(&mut self) {
+        while self.events.len() > self.retention {
+            let evicted = self.events.pop_front().unwrap();
+            self.horizon = evicted.commit_ts + 1;
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Database<K, V> {
+    /// The changes committed from now on. The receiver is both a channel and,
+    /// with [`Receiver::iter`], a blocking iterator that ends once every
+    /// handle of the database is dropped, or after an
+    /// [`Error::ChangesLagged`] once the subscriber fell [`CHANGE_BUFFER`]
+    /// events behind.
+    pub fn subscribe(&self) -> Changes<K, V> {
+        let (sender, receiver) = mpsc::sync_channel(CHANGE_BUFFER);
+        self.changes.lock().unwrap().subscribers.push(Subscriber {
+            sender,
+            lagged: None,
+        });
+        receiver
+    }
+
+    /// The changes committed at commit timestamp `from` and later, starting
+    /// with the ones still kept, see [`Database::set_change_retention`]. Those
+    /// come on top of the [`CHANGE_BUFFER`] events the subscriber may fall
+    /// behind by.
+    pub fn subscribe_from(&self, from: u64) -> Result<Changes<K, V>, Error<K>> {
+        let mut feed = self.changes.lock().unwrap();
+        if from < feed.horizon {
+            return Err(Error::ChangesOutOfRange {
+                from,
+                horizon: feed.horizon,
+            });
+        }
+        let replay: Vec<_> = feed
+            .events
+            .iter()
+            .filter(|e| e.commit_ts >= from)
+            .cloned()
+            .collect();
+        let (sender, receiver) = mpsc::sync_channel(CHANGE_BUFFER + replay.len());
+        for event in replay {
+            let _ = sender.try_send(Ok(event));
+        }
+        feed.subscribers.push(Subscriber {
+            sender,
+            lagged: None,
+        });
+        Ok(receiver)
+    }
+
+    /// Keeps the latest `events` change events for
+    /// [`Database::subscribe_from`], for every handle of the database.
+    pub fn set_change_retention(&self, events: usize) {
+        let mut feed = self.changes.lock().unwrap();
+        feed.retention = events;
+        feed.trim();
+    }
+
+    /// Sends what `tx` changed to the subscribers, once it committed, unless
+    /// it changed nothing, and drops the subscribers that are gone or were
+    /// told they fell behind.
+    /// Called with the `txs_info` write lock held, so events go out in commit
+    /// order, and never blocks on a subscriber.
+    pub(crate) fn publish(
+        &self,
+        kvlist: &KVListType<K, V>,
+        txs_info: &TxInfo<K>,
+        tx: &Transaction<K>,
+    ) {
+        let mut feed = self.changes.lock().unwrap();
+        if feed.subscribers.is_empty() && feed.retention == 0 {
+            feed.start_after(tx.commit_ts);
+            return;
+        }
+        let event = ChangeEvent {
+            tx_id: tx.id,
+            commit_ts: tx.commit_ts,
+            changes: tx
+                .write_set
+                .iter()
+                .filter_map(|key| Self::change(kvlist.get(key)?, txs_info, tx, key))
+                .collect(),
+        };
+        if event.changes.is_empty() {
+            return;
+        }
+        feed.subscribers
+            .retain_mut(|subscriber| subscriber.send(&event));
+        feed.events.push_back(event);
+        feed.trim();
+    }
+
+    /// What `tx` did to `key`: its last version against the newest one
+    /// committed before it, nothing if it deleted a key it created.
+    fn change(
+        values: &[Value<V>],
+        txs_info: &TxInfo<K>,
+        tx: &Transaction<K>,
+        key: &K,
+    ) -> Option<Change<K, V>> {
+        let new = values
+            .iter()
+            .rfind(|v| v.tx_start_id == tx.id && v.tx_end_id != tx.id)
+            .map(|v| v.data.clone());
+        let old = values
+            .iter()
+            .filter(|v| {
+                v.tx_start_id != tx.id
+                    && (v.tx_end_id == tx.id || txs_info.committed_at(v.tx_end_id).is_none())
+            })
+            .filter_map(|v| Some((txs_info.committed_at(v.tx_start_id)?, v)))
+            .max_by_key(|(commit_ts, _)| *commit_ts)
+            .map(|(_, v)| v.data.clone());
+        let key = key.clone();
+        match (old, new) {
+            (old, Some(new)) => Some(Change::Put { key, old, new }),
+            (Some(old), None) => Some(Change::Delete { key, old }),
+            (None, None) => None,
+        }
+    }
+}
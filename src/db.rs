@@ -1,21 +1,29 @@
+use crate::cdc::*;
 #[allow(unused)]
 use crate::debug_info;
+use crate::error::*;
+use crate::lock::*;
+use crate::observe::*;
+use crate::stats::*;
 use crate::tx::*;
 #[allow(unused)]
 use crate::utils::*;
+use crate::wal::*;
 use std::{
-    cell::RefCell,
     collections::{BTreeMap, BTreeSet},
-    rc::Rc,
+    io,
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
 };
 
 pub type TxIdType = u64;
 pub type KeyType = String;
 pub type ValueType = String;
-pub type KVListType = BTreeMap<KeyType, Vec<Value>>;
-pub type TXListType = BTreeMap<TxIdType, Rc<RefCell<Transaction>>>;
+pub type KVListType<K = KeyType, V = ValueType> = BTreeMap<K, Vec<Value<V>>>;
+pub type TXListType<K = KeyType> = BTreeMap<TxIdType, Arc<RwLock<Transaction<K>>>>;
 
-#[derive(PartialEq, Clone, Debug)]
+/// Which anomalies each level prevents is tabulated in `tests/anomalies.rs`.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
 pub enum IsolationLevel {
     ReadUncommitted,
     ReadCommitted,
@@ -24,169 +32,750 @@ pub enum IsolationLevel {
     Serializable,
 }
 
+/// How RepeatableRead, Snapshot and Serializable transactions tell which
+/// commits they see.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum SnapshotMode {
+    /// Every commit with a timestamp up to the last one before the
+    /// transaction began.
+    #[default]
+    CommitTimestamp,
+    /// Every commit of a transaction that began before, except those still
+    /// running then, which are copied into `Transaction.inprogress`. Kept to
+    /// compare against.
+    ActiveSet,
+}
+
+/// When a Snapshot or Serializable transaction finds out that a concurrent
+/// one wrote the same key.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum WriteConflicts {
+    /// At commit, the first committer wins.
+    #[default]
+    AtCommit,
+    /// At the write, if another transaction outside the snapshot already
+    /// wrote the key, committed or not.
+    FailFast,
+    /// At the write, first waiting for an exclusive lock on the key, as
+    /// [`Command::GetForUpdate`] does, so a writer that holds it gets to
+    /// finish. Writers that took no lock are treated as with `FailFast`.
+    Wait,
+}
+
 #[derive(Debug)]
-pub struct Value {
-    pub data: String,
+pub struct Value<V = ValueType> {
+    pub data: V,
     pub tx_start_id: TxIdType,
     pub tx_end_id: TxIdType,
 }
 
-pub struct TxInfo {
+pub struct TxInfo<K = KeyType> {
     pub next_tx_id: TxIdType,
-    pub txs: TXListType,
+    pub txs: TXListType<K>,
+    /// The timestamp of the last commit. Timestamps increase by one with
+    /// every commit, starting at 1.
+    pub commit_ts: u64,
+    /// How many of the latest commits vacuum keeps readable with
+    /// [`BeginOptions::as_of`], on top of what active transactions see. Set
+    /// with [`Database::set_history_retention`].
+    pub history_retention: u64,
+    /// The history horizon of the last vacuum. History before it may be gone
+    /// even if the retention grew since.
+    pub vacuumed_to: u64,
 }
 
-pub struct Database {
-    pub kvs_info: Rc<RefCell<KVListType>>,
-    pub txs_info: Rc<RefCell<TxInfo>>,
+impl<K> TxInfo<K> {
+    /// Transactions missing from `txs` below `next_tx_id` were pruned by
+    /// vacuum, which only prunes aborted transactions once nothing refers to
+    /// them any more, so the ones still referenced are committed.
+    pub fn get_transaction_state(&self, tx_id: TxIdType) -> Option<TransactionState> {
+        match self.txs.get(&tx_id) {
+            Some(tx) => Some(tx.read().unwrap().state.clone()),
+            None if tx_id > 0 && tx_id < self.next_tx_id => Some(TransactionState::Committed),
+            None => None,
+        }
+    }
+
+    /// When `tx_id` committed, `None` if it has not. Pruned transactions
+    /// committed before every snapshot still around, as if at 0.
+    pub fn committed_at(&self, tx_id: TxIdType) -> Option<u64> {
+        match self.txs.get(&tx_id) {
+            Some(tx) => {
+                let tx = tx.read().unwrap();
+                (tx.state == TransactionState::Committed).then_some(tx.commit_ts)
+            }
+            None if tx_id > 0 && tx_id < self.next_tx_id => Some(0),
+            None => None,
+        }
+    }
+
+    /// Whether `tx_id` committed and `tx` sees it.
+    pub fn in_snapshot(&self, tx: &Transaction<K>, tx_id: TxIdType) -> bool {
+        self.committed_at(tx_id)
+            .is_some_and(|commit_ts| tx.sees(tx_id, commit_ts))
+    }
+
+    /// Every other transaction whose commit `tx` does not see, whether it
+    /// committed or not.
+    pub(crate) fn concurrent(&self, tx: &Transaction<K>) -> Vec<TxIdType> {
+        self.txs
+            .keys()
+            .copied()
+            .filter(|tx_id| *tx_id != tx.id && !self.in_snapshot(tx, *tx_id))
+            .collect()
+    }
+
+    /// The oldest transaction id any active or future snapshot, or any
+    /// snapshot as of `history_horizon` or later, can still treat as in
+    /// progress. Everything finished below it looks the same to every such
+    /// snapshot.
+    pub fn oldest_snapshot(&self, history_horizon: u64) -> TxIdType {
+        let mut oldest = self.next_tx_id;
+        let mut oldest_ts = history_horizon;
+        for tx in self.txs.values() {
+            let tx = tx.read().unwrap();
+            if tx.state == TransactionState::Active {
+                oldest = oldest.min(tx.inprogress.first().copied().unwrap_or(tx.id));
+                oldest_ts = oldest_ts.min(tx.snapshot_ts);
+            }
+        }
+        // A commit after the oldest snapshot was taken is not seen by it.
+        for tx in self.txs.values() {
+            let tx = tx.read().unwrap();
+            if tx.state == TransactionState::Committed && tx.commit_ts > oldest_ts {
+                oldest = oldest.min(tx.id);
+            }
+        }
+        oldest
+    }
+
+    /// The oldest commit timestamp [`BeginOptions::as_of`] may still ask for.
+    pub fn history_horizon(&self) -> u64 {
+        self.commit_ts
+            .saturating_sub(self.history_retention)
+            .max(self.vacuumed_to)
+    }
+
+    fn commit(&mut self, tx: &mut Transaction<K>) {
+        self.commit_ts += 1;
+        tx.commit_ts = self.commit_ts;
+        tx.state = TransactionState::Committed;
+    }
+
+    pub(crate) fn get_active_tx(&self) -> BTreeSet<TxIdType> {
+        self.txs
+            .iter()
+            .filter(|(_, tx)| tx.read().unwrap().state == TransactionState::Active)
+            .map(|(tx_id, _)| *tx_id)
+            .collect()
+    }
+}
+
+/// A cheaply cloneable handle to the store.
+///
+/// Clones share the same data, so a clone can be moved to another thread and
+/// used to open connections there. Locks are always taken in the order
+/// `kvs_info` -> `txs_info` -> a single `Transaction`, the lock table and the
+/// transaction counters last.
+///
+/// Keys and values default to strings, any `K: Ord + Clone` and `V: Clone`
+/// work as well.
+pub struct Database<K = KeyType, V = ValueType> {
+    pub kvs_info: Arc<RwLock<KVListType<K, V>>>,
+    pub txs_info: Arc<RwLock<TxInfo<K>>>,
     pub default_isolation_level: IsolationLevel,
+    pub write_conflicts: WriteConflicts,
+    pub snapshot_mode: SnapshotMode,
+    pub locks: Arc<LockManager<K>>,
+    /// What [`Command::GetForUpdate`] and [`Command::GetForShare`] do when the
+    /// key is locked by another transaction.
+    pub lock_wait_policy: WaitPolicy,
+    /// Which transaction is aborted when lock waits form a cycle.
+    pub deadlock_victim: DeadlockVictim,
+    /// What transactions, commands and visibility checks are reported to.
+    pub observer: Option<Arc<dyn Observer<K, V>>>,
+    pub(crate) counters: Arc<Mutex<Counters>>,
+    pub(crate) changes: Arc<Mutex<ChangeFeed<K, V>>>,
+    wal: Option<Arc<Wal<K, V>>>,
 }
 
-impl Database {
+impl<K, V> Clone for Database<K, V> {
+    fn clone(&self) -> Self {
+        Database {
+            kvs_info: Arc::clone(&self.kvs_info),
+            txs_info: Arc::clone(&self.txs_info),
+            default_isolation_level: self.default_isolation_level.clone(),
+            write_conflicts: self.write_conflicts,
+            snapshot_mode: self.snapshot_mode,
+            locks: Arc::clone(&self.locks),
+            lock_wait_policy: self.lock_wait_policy,
+            deadlock_victim: self.deadlock_victim,
+            observer: self.observer.clone(),
+            counters: Arc::clone(&self.counters),
+            changes: Arc::clone(&self.changes),
+            wal: self.wal.clone(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Default for Database<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Codec + Ord + Clone, V: Codec + Clone> Database<K, V> {
+    /// Opens a durable database backed by the write-ahead log at `path`,
+    /// syncing it after every commit.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Database::open_with(path, SyncPolicy::Always)
+    }
+
+    /// Opens a durable database backed by the write-ahead log at `path`.
+    ///
+    /// The log is replayed to rebuild the version chains and transaction
+    /// states. Transactions that were still active when the log ends never
+    /// committed, so they are aborted.
+    pub fn open_with<P: AsRef<Path>>(path: P, policy: SyncPolicy) -> io::Result<Self> {
+        let (wal, records) = Wal::open(path.as_ref(), policy)?;
+        let mut db = Database::new();
+        for record in records {
+            db.replay(record)?;
+        }
+
+        db.wal = Some(Arc::new(wal));
+        // Counting and the change stream start from the reopened database,
+        // not its log.
+        *db.counters.lock().unwrap() = Default::default();
+        let commit_ts = db.txs_info.read().unwrap().commit_ts;
+        db.changes.lock().unwrap().start_after(commit_ts);
+        let active = db.txs_info.read().unwrap().get_active_tx();
+        for tx_id in active {
+            db.finish_transaction(tx_id, TransactionState::Aborted, AbortReason::Recovery)
+                .map_err(Error::into_io)?;
+        }
+        Ok(db)
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Database<K, V> {
     pub fn new() -> Self {
         Database {
-            kvs_info: Rc::new(RefCell::new(Default::default())),
-            txs_info: Rc::new(RefCell::new(TxInfo {
+            kvs_info: Arc::new(RwLock::new(Default::default())),
+            txs_info: Arc::new(RwLock::new(TxInfo {
                 next_tx_id: 1,
                 txs: Default::default(),
+                commit_ts: 0,
+                history_retention: 0,
+                vacuumed_to: 0,
             })),
             default_isolation_level: IsolationLevel::ReadUncommitted,
+            write_conflicts: Default::default(),
+            snapshot_mode: Default::default(),
+            locks: Default::default(),
+            lock_wait_policy: Default::default(),
+            deadlock_victim: Default::default(),
+            observer: None,
+            counters: Default::default(),
+            changes: Default::default(),
+            wal: None,
+        }
+    }
+
+    fn replay(&self, record: Record<K, V>) -> io::Result<()> {
+        match record {
+            Record::Begin {
+                tx_id,
+                isolation_level,
+            } => {
+                let options = BeginOptions {
+                    isolation: Some(isolation_level),
+                    ..Default::default()
+                };
+                let tx = self.begin_transaction(options).map_err(Error::into_io)?;
+                if tx.read().unwrap().id != tx_id {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unexpected transaction id {} in log", tx_id),
+                    ));
+                }
+            }
+            Record::Set { tx_id, key, value } => {
+                self.set_value(&self.logged_transaction(tx_id)?, key, value)
+                    .map_err(Error::into_io)?;
+            }
+            Record::Delete { tx_id, key } => {
+                self.delete_value(&self.logged_transaction(tx_id)?, key)
+                    .map_err(Error::into_io)?;
+            }
+            Record::Commit { tx_id } => {
+                let tx = self.logged_transaction(tx_id)?;
+                let mut kvlist = self.kvs_info.write().unwrap();
+                let mut txs_info = self.txs_info.write().unwrap();
+                Self::end_again(&mut kvlist, &txs_info, &tx.read().unwrap());
+                txs_info.commit(&mut tx.write().unwrap());
+            }
+            Record::Abort { tx_id } => {
+                self.logged_transaction(tx_id)?.write().unwrap().state = TransactionState::Aborted;
+            }
+            Record::Savepoint { tx_id, name } => {
+                self.savepoint(&self.logged_transaction(tx_id)?, name)
+                    .map_err(Error::into_io)?;
+            }
+            Record::RollbackTo { tx_id, name } => {
+                self.rollback_to(&self.logged_transaction(tx_id)?, name)
+                    .map_err(Error::into_io)?;
+            }
+            Record::Release { tx_id, name } => {
+                self.release(&self.logged_transaction(tx_id)?, name)
+                    .map_err(Error::into_io)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn logged_transaction(&self, tx_id: TxIdType) -> io::Result<Arc<RwLock<Transaction<K>>>> {
+        self.txs_info
+            .read()
+            .unwrap()
+            .txs
+            .get(&tx_id)
+            .cloned()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown transaction id {} in log", tx_id),
+                )
+            })
+    }
+
+    pub(crate) fn log(&self, record: Record<K, V>) -> Result<(), Error<K>> {
+        if let Some(wal) = &self.wal {
+            wal.append(&record).map_err(|e| Error::Io(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    pub fn new_connection(&self) -> Connection<K, V> {
+        Connection {
+            tx: None,
+            db: self.clone(),
+            history: None,
         }
     }
 
-    pub fn new_connection(&self) -> Connection {
-        Connection { tx: None, db: self }
+    /// Starts a transaction at the database's default isolation level.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the transaction cannot be written to the write-ahead log.
+    pub fn new_transaction(&self) -> Arc<RwLock<Transaction<K>>> {
+        self.begin_transaction(BeginOptions::default())
+            .unwrap_or_else(|e| panic!("{}", e.into_io()))
     }
 
-    pub fn new_transaction(&self) -> Rc<RefCell<Transaction>> {
-        let tx_id = self.txs_info.as_ref().borrow().next_tx_id.clone();
-        let isolation_level = self.default_isolation_level.clone();
-        let tx = Rc::new(RefCell::new(Transaction {
+    pub(crate) fn begin_transaction(
+        &self,
+        options: BeginOptions,
+    ) -> Result<Arc<RwLock<Transaction<K>>>, Error<K>> {
+        let isolation_level = match options.as_of {
+            Some(_) => IsolationLevel::Snapshot,
+            None => options
+                .isolation
+                .unwrap_or_else(|| self.default_isolation_level.clone()),
+        };
+        let mut txs_info = self.txs_info.write().unwrap();
+        if let Some(as_of) = options.as_of {
+            let horizon = txs_info.history_horizon();
+            if as_of < horizon || as_of > txs_info.commit_ts {
+                return Err(Error::AsOfOutOfRange {
+                    as_of,
+                    horizon,
+                    latest: txs_info.commit_ts,
+                });
+            }
+        }
+        let tx_id = txs_info.next_tx_id;
+        self.log(Record::Begin {
+            tx_id,
+            isolation_level: isolation_level.clone(),
+        })?;
+        let tx = Arc::new(RwLock::new(Transaction {
             id: tx_id,
             state: TransactionState::Active,
-            isolation_level: isolation_level,
-            inprogress: self.get_active_tx(),
+            isolation_level,
+            read_only: options.read_only || options.as_of.is_some(),
+            snapshot_mode: match options.as_of {
+                Some(_) => SnapshotMode::CommitTimestamp,
+                None => self.snapshot_mode,
+            },
+            snapshot_ts: options.as_of.unwrap_or(txs_info.commit_ts),
+            inprogress: match (options.as_of, self.snapshot_mode) {
+                (None, SnapshotMode::ActiveSet) => txs_info.get_active_tx(),
+                _ => Default::default(),
+            },
             write_set: Default::default(),
             read_set: Default::default(),
+            read_ranges: Default::default(),
+            in_conflicts: Default::default(),
+            out_conflicts: Default::default(),
+            ended: Default::default(),
+            commit_ts: 0,
+            undo: Default::default(),
+            savepoints: Default::default(),
         }));
-        self.txs_info.borrow_mut().next_tx_id += 1;
-        self.txs_info.borrow_mut().txs.insert(tx_id, Rc::clone(&tx));
-        tx
+        txs_info.next_tx_id += 1;
+        txs_info.txs.insert(tx_id, Arc::clone(&tx));
+        drop(txs_info);
+        let isolation = tx.read().unwrap().isolation_level.clone();
+        self.counters.lock().unwrap().begun(&isolation);
+        self.emit(|| Event::Begin { tx_id, isolation });
+        Ok(tx)
     }
 
-    fn get_active_tx(&self) -> BTreeSet<TxIdType> {
-        self.txs_info
-            .as_ref()
-            .borrow()
-            .txs
+    /// Keeps the latest `commits` readable with [`BeginOptions::as_of`], for
+    /// every handle of the database and the vacuum run through any of them.
+    pub fn set_history_retention(&self, commits: u64) {
+        self.txs_info.write().unwrap().history_retention = commits;
+    }
+
+    /// Writes a new version of `key`. Returns the transaction that wrote the
+    /// version it replaced, if one was visible.
+    pub(crate) fn set_value(
+        &self,
+        tx: &Arc<RwLock<Transaction<K>>>,
+        key: K,
+        val: V,
+    ) -> Result<Option<TxIdType>, Error<K>> {
+        let mut kvlist = self.kvs_info.write().unwrap();
+        let txs_info = self.txs_info.read().unwrap();
+        let mut tx = tx.write().unwrap();
+        if tx.read_only {
+            return Err(Error::ReadOnly(tx.id));
+        }
+        self.log(Record::Set {
+            tx_id: tx.id,
+            key: key.clone(),
+            value: val.clone(),
+        })?;
+        tx.write_set.insert(key.clone());
+        let values = kvlist.entry(key.clone()).or_default();
+        let replaced = Self::newest_visible(&txs_info, &tx, values);
+        let ended = Self::end_visible(&txs_info, &mut tx, &key, values);
+        values.push(Value {
+            data: val,
+            tx_start_id: tx.id,
+            tx_end_id: 0,
+        });
+        Self::record_undo(&mut tx, key, true, ended);
+        Ok(replaced)
+    }
+
+    /// Ends every version of `key` visible to `tx`. Returns the transaction
+    /// that wrote the version it deleted, `None` if the key does not exist,
+    /// and fails if the key exists but none of its versions is visible.
+    pub(crate) fn delete_value(
+        &self,
+        tx: &Arc<RwLock<Transaction<K>>>,
+        key: K,
+    ) -> Result<Option<TxIdType>, Error<K>> {
+        let mut kvlist = self.kvs_info.write().unwrap();
+        let txs_info = self.txs_info.read().unwrap();
+        let mut tx = tx.write().unwrap();
+        if tx.read_only {
+            return Err(Error::ReadOnly(tx.id));
+        }
+        let values = match kvlist.get_mut(&key) {
+            Some(values) => values,
+            None => return Ok(None),
+        };
+        let Some(replaced) = Self::newest_visible(&txs_info, &tx, values) else {
+            return Err(Error::KeyNotFound { key });
+        };
+        self.log(Record::Delete {
+            tx_id: tx.id,
+            key: key.clone(),
+        })?;
+        let ended = Self::end_visible(&txs_info, &mut tx, &key, values);
+        tx.write_set.insert(key.clone());
+        Self::record_undo(&mut tx, key, false, ended);
+        Ok(Some(replaced))
+    }
+
+    /// The version of `key` that `tx` reads: the newest visible one, or the
+    /// newest of its own once it wrote the key.
+    pub(crate) fn read_version<'a>(
+        txs_info: &TxInfo<K>,
+        tx: &Transaction<K>,
+        key: &K,
+        values: &'a [Value<V>],
+    ) -> Option<&'a Value<V>> {
+        let own = tx.write_set.contains(key);
+        values
             .iter()
-            .filter(|(_, tx)| tx.as_ref().borrow().state == TransactionState::Active)
-            .map(|(tx_id, _)| *tx_id)
-            .collect()
+            .rfind(|v| Self::readable(txs_info, tx, own, v))
     }
 
-    fn set_share_item(set1: &BTreeSet<KeyType>, set2: &BTreeSet<KeyType>) -> bool {
-        set1.iter().any(|item| set2.contains(item))
+    /// Whether `tx` may read `val`, a version of a key it wrote if `own`:
+    /// the check [`Database::read_version`] makes of each version.
+    pub(crate) fn readable(
+        txs_info: &TxInfo<K>,
+        tx: &Transaction<K>,
+        own: bool,
+        val: &Value<V>,
+    ) -> bool {
+        (!own || val.tx_start_id == tx.id) && Self::visible(txs_info, tx, val)
     }
 
-    fn conflict_check<F>(&self, tx: &Rc<RefCell<Transaction>>, conflict_func: F) -> bool
+    /// Ends again the versions `tx` ended that a transaction which did not
+    /// commit ended since, so they stay ended once `tx` commits. That
+    /// includes the versions of other writers whose end vacuum cleared after
+    /// such a transaction aborted. An unended version of `tx` is the one it
+    /// wrote last, not one it ended.
+    fn end_again(kvlist: &mut KVListType<K, V>, txs_info: &TxInfo<K>, tx: &Transaction<K>) {
+        for (key, writer) in &tx.ended {
+            for v in kvlist.get_mut(key).into_iter().flatten() {
+                if v.tx_start_id == *writer
+                    && v.tx_end_id != tx.id
+                    && (v.tx_end_id != 0 || *writer != tx.id)
+                    && txs_info.get_transaction_state(v.tx_end_id)
+                        != Some(TransactionState::Committed)
+                {
+                    v.tx_end_id = tx.id;
+                }
+            }
+        }
+    }
+
+    /// The transaction that wrote the newest version of `values` visible to
+    /// `tx`.
+    fn newest_visible(
+        txs_info: &TxInfo<K>,
+        tx: &Transaction<K>,
+        values: &[Value<V>],
+    ) -> Option<TxIdType> {
+        values
+            .iter()
+            .rfind(|v| Self::visible(txs_info, tx, v))
+            .map(|v| v.tx_start_id)
+    }
+
+    fn set_share_item(set1: &BTreeSet<K>, set2: &BTreeSet<K>) -> Option<K> {
+        set1.iter().find(|item| set2.contains(*item)).cloned()
+    }
+
+    /// Returns the first key `conflict_func` reports for a concurrent
+    /// transaction that already committed, along with that transaction's id.
+    pub(crate) fn conflict_check<F>(
+        txs_info: &TxInfo<K>,
+        tx: &Transaction<K>,
+        conflict_func: F,
+    ) -> Option<(K, TxIdType)>
     where
-        F: Fn(Rc<RefCell<Transaction>>, Rc<RefCell<Transaction>>) -> bool,
+        F: Fn(&Transaction<K>, &Transaction<K>) -> Option<K>,
     {
-        {
-            for tx_id in tx.as_ref().borrow().inprogress.iter() {
-                if let Some(tx_other) = self.txs_info.as_ref().borrow().txs.get(&tx_id) {
-                    if tx_other.as_ref().borrow().state == TransactionState::Committed
-                        && conflict_func(Rc::clone(&tx), Rc::clone(tx_other))
-                    {
-                        return true;
+        for tx_id in txs_info.concurrent(tx) {
+            if let Some(tx_other) = txs_info.txs.get(&tx_id) {
+                let tx_other = tx_other.read().unwrap();
+                if tx_other.state == TransactionState::Committed {
+                    if let Some(key) = conflict_func(tx, &tx_other) {
+                        return Some((key, tx_id));
                     }
                 }
             }
         }
 
+        None
+    }
+
+    /// Aborts `tx` before it writes `key` if the first updater of the key is
+    /// a concurrent transaction, as configured by `write_conflicts`.
+    pub(crate) fn first_updater_check(
+        &self,
+        tx: &Arc<RwLock<Transaction<K>>>,
+        key: &K,
+    ) -> Result<(), Error<K>> {
         {
-            for tx_id in tx.as_ref().borrow().id..self.txs_info.as_ref().borrow().next_tx_id {
-                if let Some(tx_other) = self.txs_info.as_ref().borrow().txs.get(&tx_id) {
-                    if tx_other.as_ref().borrow().state == TransactionState::Committed
-                        && conflict_func(Rc::clone(&tx), Rc::clone(tx_other))
-                    {
-                        return true;
-                    }
-                }
+            let tx = tx.read().unwrap();
+            let checked = matches!(
+                tx.isolation_level,
+                IsolationLevel::Snapshot | IsolationLevel::Serializable
+            );
+            if !checked || tx.read_only || self.write_conflicts == WriteConflicts::AtCommit {
+                return Ok(());
             }
         }
+        if self.write_conflicts == WriteConflicts::Wait {
+            self.lock_key(tx, key, LockMode::Exclusive)?;
+        }
 
-        false
+        let (tx_id, writer) = {
+            let txs_info = self.txs_info.read().unwrap();
+            let tx = tx.read().unwrap();
+            let writer = txs_info.concurrent(&tx).into_iter().find(|tx_id| {
+                txs_info.txs.get(tx_id).is_some_and(|other| {
+                    let other = other.read().unwrap();
+                    other.state != TransactionState::Aborted && other.write_set.contains(key)
+                })
+            });
+            (tx.id, writer)
+        };
+        if let Some(other_tx) = writer {
+            self.finish_transaction(
+                tx_id,
+                TransactionState::Aborted,
+                AbortReason::WriteWriteConflict,
+            )?;
+            return Err(Error::WriteWriteConflict {
+                key: key.clone(),
+                other_tx,
+            });
+        }
+        Ok(())
     }
 
     pub fn complete_transaction(
         &self,
         tx_id: TxIdType,
         state: TransactionState,
-    ) -> Result<(), String> {
-        if let Some(tx) = self.txs_info.as_ref().borrow().txs.get(&tx_id) {
-            match state {
-                TransactionState::Committed => {
-                    {
-                        if tx.as_ref().borrow().isolation_level == IsolationLevel::Snapshot
-                            && self.conflict_check(tx, |t1, t2| {
-                                Database::set_share_item(
-                                    &t1.as_ref().borrow().write_set,
-                                    &t2.as_ref().borrow().write_set,
-                                )
-                            })
-                        {
-                            self.complete_transaction(tx_id, TransactionState::Aborted)?;
-                            return Err("Write-Write Conflict".to_string());
-                        }
-
-                        if tx.as_ref().borrow().isolation_level == IsolationLevel::Serializable
-                            && self.conflict_check(tx, |t1, t2| {
-                                Database::set_share_item(
-                                    &t1.as_ref().borrow().read_set,
-                                    &t2.as_ref().borrow().write_set,
-                                )
-                            })
-                        {
-                            self.complete_transaction(tx_id, TransactionState::Aborted)?;
-                            return Err("Read-Write Conflict".to_string());
-                        }
+    ) -> Result<(), Error<K>> {
+        self.finish_transaction(tx_id, state, AbortReason::User)
+    }
+
+    /// Same as [`Database::complete_transaction`], counting an abort under
+    /// `reason`. Failing to commit counts under the reason it failed for.
+    pub(crate) fn finish_transaction(
+        &self,
+        tx_id: TxIdType,
+        state: TransactionState,
+        reason: AbortReason,
+    ) -> Result<(), Error<K>> {
+        let outcome = self.finish(tx_id, state.clone(), reason);
+        self.emit(|| Event::Complete {
+            tx_id,
+            state,
+            outcome: outcome.clone(),
+        });
+        outcome
+    }
+
+    fn finish(
+        &self,
+        tx_id: TxIdType,
+        state: TransactionState,
+        reason: AbortReason,
+    ) -> Result<(), Error<K>> {
+        // Holding the write locks for the whole check makes validation and the
+        // state change atomic with respect to other committers and writers.
+        let mut kvlist = self.kvs_info.write().unwrap();
+        let mut txs_info = self.txs_info.write().unwrap();
+        let tx = match txs_info.txs.get(&tx_id) {
+            Some(tx) => Arc::clone(tx),
+            None => return Err(Error::TransactionNotFound(tx_id)),
+        };
+        let current = tx.read().unwrap().state.clone();
+        if current != TransactionState::Active {
+            return Err(Error::InvalidState {
+                tx_id,
+                state: current,
+            });
+        }
+        match state {
+            TransactionState::Committed => {
+                let isolation_level = tx.read().unwrap().isolation_level.clone();
+                let conflict = match isolation_level {
+                    IsolationLevel::Snapshot | IsolationLevel::Serializable => {
+                        let tx = tx.read().unwrap();
+                        Self::conflict_check(&txs_info, &tx, |t1, t2| {
+                            Self::set_share_item(&t1.write_set, &t2.write_set)
+                        })
+                        .map(|(key, other_tx)| Error::WriteWriteConflict { key, other_tx })
                     }
-                    {
-                        tx.borrow_mut().state = state.clone()
+                    _ => None,
+                };
+                let conflict = match isolation_level {
+                    IsolationLevel::Serializable => {
+                        conflict.or_else(|| Self::serializable_check(&txs_info, &tx))
                     }
+                    _ => conflict,
+                };
+                if let Some(conflict) = conflict {
+                    let reason = match conflict {
+                        Error::ReadWriteConflict { .. } => AbortReason::ReadWriteConflict,
+                        _ => AbortReason::WriteWriteConflict,
+                    };
+                    self.abort(&tx, tx_id, reason);
+                    return Err(conflict);
                 }
-                TransactionState::Aborted => tx.borrow_mut().state = state.clone(),
-                _ => return Err("Invalid transaction state".to_string()),
+                if let Err(e) = self.log(Record::Commit { tx_id }) {
+                    self.abort(&tx, tx_id, AbortReason::Io);
+                    return Err(e);
+                }
+                Self::end_again(&mut kvlist, &txs_info, &tx.read().unwrap());
+                txs_info.commit(&mut tx.write().unwrap());
+                self.publish(&kvlist, &txs_info, &tx.read().unwrap());
+                self.locks.release_all(tx_id);
+                self.counters.lock().unwrap().committed(&isolation_level);
             }
-            return Ok(());
+            TransactionState::Aborted => self.abort(&tx, tx_id, reason),
+            _ => return Err(Error::InvalidState { tx_id, state }),
         }
-        return Err("Transaction not found".to_string());
+        Ok(())
+    }
+
+    fn abort(&self, tx: &Arc<RwLock<Transaction<K>>>, tx_id: TxIdType, reason: AbortReason) {
+        // A missing abort record is harmless: replay aborts every transaction
+        // that has no commit record.
+        let _ = self.log(Record::Abort { tx_id });
+        let isolation_level = {
+            let mut tx = tx.write().unwrap();
+            tx.state = TransactionState::Aborted;
+            tx.isolation_level.clone()
+        };
+        self.locks.release_all(tx_id);
+        self.counters
+            .lock()
+            .unwrap()
+            .aborted(&isolation_level, reason);
     }
 
     fn get_transaction_state(&self, tx_id: TxIdType) -> Option<TransactionState> {
-        if let Some(tx) = self.txs_info.as_ref().borrow().txs.get(&tx_id) {
-            return Some(tx.as_ref().borrow().state.clone());
+        self.txs_info.read().unwrap().get_transaction_state(tx_id)
+    }
+
+    /// Checks that `tx_id` names a transaction that is still active.
+    pub fn assert_transaction(&self, tx_id: TxIdType) -> Result<(), Error<K>> {
+        match self.get_transaction_state(tx_id) {
+            Some(TransactionState::Active) => Ok(()),
+            Some(state) => Err(Error::InvalidState { tx_id, state }),
+            None => Err(Error::TransactionNotFound(tx_id)),
         }
-        None
     }
 
-    pub fn assert_transaction(&self, tx_id: TxIdType) {
-        assert!(tx_id > 0, "Invalid transaction id, must be greater than 0");
-        assert!(
-            self.get_transaction_state(tx_id) == Some(TransactionState::Active),
-            "Transaction is not active"
-        );
+    pub fn is_visible(&self, tx: &Arc<RwLock<Transaction<K>>>, val: &Value<V>) -> bool {
+        let (tx_id, visible) = {
+            let txs_info = self.txs_info.read().unwrap();
+            let tx = tx.read().unwrap();
+            (tx.id, Self::visible(&txs_info, &tx, val))
+        };
+        self.emit(|| Event::Visibility {
+            tx_id,
+            key: None,
+            tx_start_id: val.tx_start_id,
+            tx_end_id: val.tx_end_id,
+            visible,
+        });
+        visible
     }
 
-    pub fn is_visible(&self, tx: &Rc<RefCell<Transaction>>, val: &Value) -> bool {
-        let tx = tx.borrow_mut();
+    /// Same as [`Database::is_visible`], for callers that already hold the
+    /// `txs_info` lock and a borrow of the transaction.
+    pub(crate) fn visible(txs_info: &TxInfo<K>, tx: &Transaction<K>, val: &Value<V>) -> bool {
         match tx.isolation_level {
             IsolationLevel::ReadUncommitted => true,
             IsolationLevel::ReadCommitted => {
-                if val.tx_end_id != tx.id
-                    && self.get_transaction_state(val.tx_end_id)
+                if val.tx_start_id != tx.id
+                    && txs_info.get_transaction_state(val.tx_start_id)
                         != Some(TransactionState::Committed)
                 {
                     return false;
@@ -197,10 +786,10 @@ impl Database {
                 }
 
                 if val.tx_end_id > 0
-                    && self.get_transaction_state(val.tx_end_id)
+                    && txs_info.get_transaction_state(val.tx_end_id)
                         == Some(TransactionState::Committed)
                 {
-                    return true;
+                    return false;
                 }
 
                 true
@@ -208,18 +797,7 @@ impl Database {
             IsolationLevel::RepeatableRead
             | IsolationLevel::Snapshot
             | IsolationLevel::Serializable => {
-                if val.tx_start_id > tx.id {
-                    return false;
-                }
-
-                if tx.inprogress.contains(&val.tx_start_id) {
-                    return false;
-                }
-
-                if val.tx_start_id != tx.id
-                    && self.get_transaction_state(val.tx_start_id)
-                        != Some(TransactionState::Committed)
-                {
+                if val.tx_start_id != tx.id && !txs_info.in_snapshot(tx, val.tx_start_id) {
                     return false;
                 }
 
@@ -227,12 +805,7 @@ impl Database {
                     return false;
                 }
 
-                if val.tx_end_id < tx.id
-                    && val.tx_end_id > 0
-                    && self.get_transaction_state(val.tx_end_id)
-                        == Some(TransactionState::Committed)
-                    && !tx.inprogress.contains(&val.tx_end_id)
-                {
+                if val.tx_end_id > 0 && txs_info.in_snapshot(tx, val.tx_end_id) {
                     return false;
                 }
 
@@ -0,0 +1,203 @@
+use crate::db::*;
+use crate::tx::*;
+use std::{fmt, io};
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Error<K = KeyType> {
+    /// No version of the key is visible to the transaction.
+    KeyNotFound { key: K },
+    /// A concurrent transaction committed a write to a key this one wrote.
+    WriteWriteConflict { key: K, other_tx: TxIdType },
+    /// A concurrent transaction committed a write to a key this one read.
+    ReadWriteConflict { key: K, other_tx: TxIdType },
+    /// The connection has no transaction to run the command in.
+    NoActiveTransaction,
+    /// The transaction id is unknown to the database.
+    TransactionNotFound(TxIdType),
+    /// The transaction is not in a state that allows the operation, e.g. it
+    /// already committed.
+    InvalidState {
+        tx_id: TxIdType,
+        state: TransactionState,
+    },
+    /// Another transaction holds a conflicting lock on the key.
+    LockConflict { key: K, holder: TxIdType },
+    /// The key stayed locked by other transactions for too long.
+    LockTimeout { key: K },
+    /// The transaction was aborted to break a deadlock while waiting for the
+    /// lock on the key.
+    Deadlock { key: K, tx_id: TxIdType },
+    /// A time-travel read asked for a commit timestamp before the retained
+    /// history or after the latest commit.
+    AsOfOutOfRange {
+        as_of: u64,
+        horizon: u64,
+        latest: u64,
+    },
+    /// A change stream asked for commits before the oldest one still kept.
+    ChangesOutOfRange { from: u64, horizon: u64 },
+    /// A change subscriber fell too far behind and was dropped. The events
+    /// from commit timestamp `from` on were not sent to it.
+    ChangesLagged { from: u64 },
+    /// The transaction has no savepoint with that name.
+    SavepointNotFound { tx_id: TxIdType, name: String },
+    /// The transaction was started read-only and tried to write.
+    ReadOnly(TxIdType),
+    /// The write-ahead log could not be written.
+    Io(String),
+}
+
+impl<K> Error<K> {
+    /// Whether running the transaction again may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::WriteWriteConflict { .. }
+                | Error::ReadWriteConflict { .. }
+                | Error::LockConflict { .. }
+                | Error::LockTimeout { .. }
+                | Error::Deadlock { .. }
+        )
+    }
+
+    /// For the few places that only hit the write-ahead log and so need no
+    /// `Display` for the key.
+    pub(crate) fn into_io(self) -> io::Error {
+        match self {
+            Error::Io(e) => io::Error::other(e),
+            Error::TransactionNotFound(tx_id) => io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("transaction {} not found", tx_id),
+            ),
+            Error::InvalidState { tx_id, state } => io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("transaction {} is {:?}", tx_id, state),
+            ),
+            _ => io::Error::new(io::ErrorKind::InvalidData, "unexpected error in log"),
+        }
+    }
+}
+
+impl<K: DisplayKey> fmt::Display for Error<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::KeyNotFound { key } => write!(f, "key {} not found", Shown(key)),
+            Error::WriteWriteConflict { key, other_tx } => write!(
+                f,
+                "write-write conflict on key {} with transaction {}",
+                Shown(key),
+                other_tx
+            ),
+            Error::ReadWriteConflict { key, other_tx } => write!(
+                f,
+                "read-write conflict on key {} with transaction {}",
+                Shown(key),
+                other_tx
+            ),
+            Error::NoActiveTransaction => write!(f, "no active transaction"),
+            Error::TransactionNotFound(tx_id) => write!(f, "transaction {} not found", tx_id),
+            Error::InvalidState { tx_id, state } => {
+                write!(f, "transaction {} is {:?}", tx_id, state)
+            }
+            Error::LockConflict { key, holder } => {
+                write!(f, "key {} is locked by transaction {}", Shown(key), holder)
+            }
+            Error::LockTimeout { key } => {
+                write!(f, "timed out waiting for the lock on key {}", Shown(key))
+            }
+            Error::Deadlock { key, tx_id } => write!(
+                f,
+                "transaction {} was aborted to break a deadlock on key {}",
+                tx_id,
+                Shown(key)
+            ),
+            Error::AsOfOutOfRange {
+                as_of,
+                horizon,
+                latest,
+            } => write!(
+                f,
+                "cannot read as of {}, history is kept from {} to {}",
+                as_of, horizon, latest
+            ),
+            Error::ChangesOutOfRange { from, horizon } => write!(
+                f,
+                "cannot stream changes from {}, they are kept from {}",
+                from, horizon
+            ),
+            Error::ChangesLagged { from } => write!(
+                f,
+                "change subscriber fell behind, resubscribe from {}",
+                from
+            ),
+            Error::SavepointNotFound { tx_id, name } => {
+                write!(f, "transaction {} has no savepoint {}", tx_id, name)
+            }
+            Error::ReadOnly(tx_id) => write!(f, "transaction {} is read-only", tx_id),
+            Error::Io(e) => write!(f, "write-ahead log error: {}", e),
+        }
+    }
+}
+
+impl<K: fmt::Debug + DisplayKey> std::error::Error for Error<K> {}
+
+/// How a key is shown in error messages. Implemented for every key type the
+/// crate supports, including binary ones that have no `Display`.
+pub trait DisplayKey {
+    fn fmt_key(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+impl DisplayKey for String {
+    fn fmt_key(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Printable ASCII as is, other bytes escaped, e.g. `ab\xff`.
+impl DisplayKey for Vec<u8> {
+    fn fmt_key(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.escape_ascii())
+    }
+}
+
+macro_rules! display_key {
+    ($($t:ty),+) => {
+        $(impl DisplayKey for $t {
+            fn fmt_key(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self)
+            }
+        })+
+    };
+}
+
+display_key!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+macro_rules! display_tuple_key {
+    ($first:ident $(, $rest:ident)*) => {
+        impl<$first: DisplayKey $(, $rest: DisplayKey)*> DisplayKey for ($first, $($rest,)*) {
+            #[allow(non_snake_case)]
+            fn fmt_key(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let ($first, $($rest,)*) = self;
+                write!(f, "(")?;
+                $first.fmt_key(f)?;
+                $(
+                    write!(f, ", ")?;
+                    $rest.fmt_key(f)?;
+                )*
+                write!(f, ")")
+            }
+        }
+    };
+}
+
+display_tuple_key!(A, B);
+display_tuple_key!(A, B, C);
+display_tuple_key!(A, B, C, D);
+
+struct Shown<'a, K>(&'a K);
+
+impl<K: DisplayKey> fmt::Display for Shown<'_, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_key(f)
+    }
+}
@@ -0,0 +1,406 @@
+use crate::db::*;
+use crate::error::*;
+use crate::tx::*;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+    sync::{Arc, Mutex, RwLock},
+};
+
+/// What a recorded command did.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Operation<K = KeyType, V = ValueType> {
+    Begin {
+        isolation: IsolationLevel,
+    },
+    /// A [`Command::Get`] or one of its locking forms. `writer` is the
+    /// transaction that wrote the version read, `None` if none was visible.
+    Read {
+        key: K,
+        value: Option<V>,
+        writer: Option<TxIdType>,
+    },
+    /// A [`Command::Set`], or a [`Command::Delete`] if `value` is `None`.
+    /// `replaced` is the transaction that wrote the version it replaced,
+    /// `None` if none was visible.
+    Write {
+        key: K,
+        value: Option<V>,
+        replaced: Option<TxIdType>,
+    },
+    Savepoint(String),
+    RollbackTo(String),
+    Release(String),
+    Commit {
+        commit_ts: u64,
+    },
+    /// An abort, or a command that failed and aborted the transaction.
+    Abort,
+}
+
+/// One recorded command, with the error it failed with if it did.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Event<K = KeyType, V = ValueType> {
+    pub tx_id: TxIdType,
+    pub op: Operation<K, V>,
+    pub outcome: Result<(), Error<K>>,
+}
+
+/// The commands run by every [`Connection`] whose `history` is set to a clone
+/// of this one, in the order they finished.
+///
+/// Scans are not recorded, so anomalies only seen through a scan, such as
+/// phantoms, are not reported by [`check`].
+pub struct History<K = KeyType, V = ValueType> {
+    events: Arc<Mutex<Vec<Event<K, V>>>>,
+}
+
+impl<K, V> Clone for History<K, V> {
+    fn clone(&self) -> Self {
+        History {
+            events: Arc::clone(&self.events),
+        }
+    }
+}
+
+impl<K, V> Default for History<K, V> {
+    fn default() -> Self {
+        History {
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl<K: Clone, V: Clone> History<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<Event<K, V>> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Records `command`, run by a connection whose transaction is now `tx`.
+    /// A failed command that aborted the transaction is followed by an
+    /// [`Operation::Abort`].
+    pub(crate) fn record(
+        &self,
+        tx: Option<&Arc<RwLock<Transaction<K>>>>,
+        was_active: bool,
+        command: Command<K, V>,
+        result: &Result<Response<K, V>, Error<K>>,
+        observed: Option<TxIdType>,
+    ) {
+        let begun = matches!(command, Command::Begin | Command::BeginWith(_));
+        let Some(tx) = tx else {
+            return;
+        };
+        if begun && result.is_err() {
+            return;
+        }
+        let tx = tx.read().unwrap();
+        let op = match command {
+            Command::Begin | Command::BeginWith(_) => Operation::Begin {
+                isolation: tx.isolation_level.clone(),
+            },
+            Command::Get(key) | Command::GetForUpdate(key) | Command::GetForShare(key) => {
+                let value = match result {
+                    Ok(Response::Value { value, .. }) => value.clone(),
+                    _ => None,
+                };
+                Operation::Read {
+                    key,
+                    value,
+                    writer: observed,
+                }
+            }
+            Command::Set(key, value) => Operation::Write {
+                key,
+                value: Some(value),
+                replaced: observed,
+            },
+            Command::Delete(key) => Operation::Write {
+                key,
+                value: None,
+                replaced: observed,
+            },
+            Command::Scan { .. } | Command::ScanPrefix { .. } => return,
+            Command::Savepoint(name) => Operation::Savepoint(name),
+            Command::RollbackTo(name) => Operation::RollbackTo(name),
+            Command::Release(name) => Operation::Release(name),
+            Command::Commit => Operation::Commit {
+                commit_ts: tx.commit_ts,
+            },
+            Command::Abort => Operation::Abort,
+        };
+        let aborted = was_active
+            && result.is_err()
+            && !matches!(op, Operation::Abort)
+            && tx.state == TransactionState::Aborted;
+        let mut events = self.events.lock().unwrap();
+        events.push(Event {
+            tx_id: tx.id,
+            op,
+            outcome: result.as_ref().map(|_| ()).map_err(Error::clone),
+        });
+        if aborted {
+            events.push(Event {
+                tx_id: tx.id,
+                op: Operation::Abort,
+                outcome: Ok(()),
+            });
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + PartialEq> History<K, V> {
+    /// Checks the events recorded so far, see [`check`].
+    pub fn check(&self) -> BTreeMap<Anomaly, Vec<TxIdType>> {
+        check(&self.events.lock().unwrap())
+    }
+}
+
+/// The anomalies of Adya's isolation levels that [`check`] looks for.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Anomaly {
+    /// Dirty write: a cycle of write dependencies.
+    G0,
+    /// Aborted read: a committed transaction read a version written by one
+    /// that aborted.
+    G1a,
+    /// Intermediate read: a committed transaction read a version that its
+    /// writer overwrote before committing.
+    G1b,
+    /// Circular information flow: a cycle of write and read dependencies with
+    /// at least one read dependency.
+    G1c,
+    /// A cycle with at least one anti-dependency, where a transaction did not
+    /// see a version another one installed, such as a lost update or write
+    /// skew.
+    G2Item,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Anomaly::G0 => "G0",
+            Anomaly::G1a => "G1a",
+            Anomaly::G1b => "G1b",
+            Anomaly::G1c => "G1c",
+            Anomaly::G2Item => "G2-item",
+        };
+        f.write_str(name)
+    }
+}
+
+/// What a transaction did, as far as the checker cares.
+struct TxHistory<K, V> {
+    commit_ts: Option<u64>,
+    aborted: bool,
+    reads: Vec<(K, Option<V>, Option<TxIdType>)>,
+    /// Writes that were not rolled back to a savepoint.
+    writes: Vec<(K, Option<V>, Option<TxIdType>)>,
+    savepoints: Vec<(String, usize)>,
+}
+
+impl<K, V> Default for TxHistory<K, V> {
+    fn default() -> Self {
+        TxHistory {
+            commit_ts: None,
+            aborted: false,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            savepoints: Vec::new(),
+        }
+    }
+}
+
+type Edges = BTreeMap<TxIdType, BTreeSet<TxIdType>>;
+
+fn add_edge(edges: &mut Edges, from: TxIdType, to: TxIdType) {
+    if from != to {
+        edges.entry(from).or_default().insert(to);
+    }
+}
+
+/// A path from `from` to `to` following any of `graphs`, without `to`.
+fn path(graphs: &[&Edges], from: TxIdType, to: TxIdType) -> Option<Vec<TxIdType>> {
+    let mut parents = BTreeMap::from([(from, from)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(tx_id) = queue.pop_front() {
+        if tx_id == to {
+            let mut path = Vec::new();
+            let mut tx_id = parents[&to];
+            while tx_id != from {
+                path.push(tx_id);
+                tx_id = parents[&tx_id];
+            }
+            path.push(from);
+            path.reverse();
+            return Some(path);
+        }
+        for next in graphs.iter().filter_map(|g| g.get(&tx_id)).flatten() {
+            if !parents.contains_key(next) {
+                parents.insert(*next, tx_id);
+                queue.push_back(*next);
+            }
+        }
+    }
+    None
+}
+
+/// A cycle through one of the edges in `through` that otherwise follows any
+/// of `graphs`, starting with that edge.
+fn cycle(graphs: &[&Edges], through: &Edges) -> Option<Vec<TxIdType>> {
+    through.iter().find_map(|(from, tos)| {
+        tos.iter().find_map(|to| {
+            let mut cycle = path(graphs, *to, *from)?;
+            cycle.insert(0, *from);
+            Some(cycle)
+        })
+    })
+}
+
+/// Builds the dependency graph of the committed transactions in `events` and
+/// reports the anomalies it shows, each with the transactions involved: the
+/// reader and the writer for G1a and G1b, a cycle otherwise.
+///
+/// Versions are ordered the way writes replaced each other, and a version is
+/// named by its writer and value, so like Elle the check assumes a
+/// transaction never writes the same value to a key twice. Versions written
+/// before the history started, and a key that does not exist, count as the
+/// initial version. A version deleted by another transaction also reads as
+/// the initial version, so histories that delete and then read a key can
+/// show cycles that did not happen.
+pub fn check<K: Ord + Clone, V: PartialEq + Clone>(
+    events: &[Event<K, V>],
+) -> BTreeMap<Anomaly, Vec<TxIdType>> {
+    let mut txs: BTreeMap<TxIdType, TxHistory<K, V>> = BTreeMap::new();
+    for event in events {
+        let tx = txs.entry(event.tx_id).or_default();
+        match (&event.op, &event.outcome) {
+            (Operation::Commit { .. }, Err(_)) | (Operation::Abort, Ok(())) => tx.aborted = true,
+            (_, Err(_)) => {}
+            (Operation::Begin { .. }, _) => {}
+            (Operation::Read { key, value, writer }, _) => {
+                tx.reads.push((key.clone(), value.clone(), *writer))
+            }
+            (
+                Operation::Write {
+                    key,
+                    value,
+                    replaced,
+                },
+                _,
+            ) => tx.writes.push((key.clone(), value.clone(), *replaced)),
+            (Operation::Savepoint(name), _) => tx.savepoints.push((name.clone(), tx.writes.len())),
+            (Operation::RollbackTo(name), _) => {
+                if let Some(i) = tx.savepoints.iter().rposition(|(n, _)| n == name) {
+                    tx.savepoints.truncate(i + 1);
+                    tx.writes.truncate(tx.savepoints[i].1);
+                }
+            }
+            (Operation::Release(name), _) => {
+                if let Some(i) = tx.savepoints.iter().rposition(|(n, _)| n == name) {
+                    tx.savepoints.truncate(i);
+                }
+            }
+            (Operation::Commit { commit_ts }, _) => tx.commit_ts = Some(*commit_ts),
+        }
+    }
+    let committed = |tx_id: TxIdType| txs.get(&tx_id).is_some_and(|tx| tx.commit_ts.is_some());
+
+    // The version each transaction's first write of a key replaced, skipping
+    // versions of transactions that did not commit.
+    let first_replaced = |tx_id: TxIdType, key: &K| {
+        txs.get(&tx_id).and_then(|tx| {
+            tx.writes
+                .iter()
+                .find(|(k, _, replaced)| k == key && *replaced != Some(tx_id))
+                .map(|(_, _, replaced)| *replaced)
+        })
+    };
+    let resolve = |mut version: Option<TxIdType>, key: &K| {
+        for _ in 0..=txs.len() {
+            match version {
+                Some(tx_id) if txs.contains_key(&tx_id) && !committed(tx_id) => {
+                    version = first_replaced(tx_id, key).flatten();
+                }
+                Some(tx_id) if !txs.contains_key(&tx_id) => return None,
+                _ => return version,
+            }
+        }
+        None
+    };
+    // The committed transactions that installed a version right after each
+    // version of each key.
+    let mut successors: BTreeMap<(&K, Option<TxIdType>), BTreeSet<TxIdType>> = BTreeMap::new();
+    for (tx_id, tx) in &txs {
+        if tx.commit_ts.is_none() {
+            continue;
+        }
+        let keys: BTreeSet<&K> = tx.writes.iter().map(|(key, _, _)| key).collect();
+        for key in keys {
+            let replaced = first_replaced(*tx_id, key).flatten();
+            successors
+                .entry((key, resolve(replaced, key)))
+                .or_default()
+                .insert(*tx_id);
+        }
+    }
+
+    let mut anomalies = BTreeMap::new();
+    let (mut ww, mut wr, mut rw) = (Edges::new(), Edges::new(), Edges::new());
+    for ((_, version), writers) in &successors {
+        for writer in writers {
+            if let Some(version) = version {
+                add_edge(&mut ww, *version, *writer);
+            }
+        }
+    }
+    for (tx_id, tx) in &txs {
+        if tx.commit_ts.is_none() {
+            continue;
+        }
+        for (key, value, writer) in &tx.reads {
+            let version = match writer {
+                Some(writer) if writer == tx_id => continue,
+                Some(writer) if txs.contains_key(writer) => {
+                    let writes = &txs[writer];
+                    if writes.aborted {
+                        anomalies
+                            .entry(Anomaly::G1a)
+                            .or_insert_with(|| vec![*tx_id, *writer]);
+                        continue;
+                    }
+                    if writes.commit_ts.is_none() {
+                        continue;
+                    }
+                    let last = writes.writes.iter().rfind(|(k, _, _)| k == key);
+                    if last.map(|(_, v, _)| v) != Some(value) {
+                        anomalies
+                            .entry(Anomaly::G1b)
+                            .or_insert_with(|| vec![*tx_id, *writer]);
+                    }
+                    add_edge(&mut wr, *writer, *tx_id);
+                    Some(*writer)
+                }
+                _ => None,
+            };
+            for next in successors.get(&(key, version)).into_iter().flatten() {
+                add_edge(&mut rw, *tx_id, *next);
+            }
+        }
+    }
+
+    if let Some(cycle) = cycle(&[&ww], &ww) {
+        anomalies.insert(Anomaly::G0, cycle);
+    }
+    if let Some(cycle) = cycle(&[&ww, &wr], &wr) {
+        anomalies.insert(Anomaly::G1c, cycle);
+    }
+    if let Some(cycle) = cycle(&[&ww, &wr, &rw], &rw) {
+        anomalies.insert(Anomaly::G2Item, cycle);
+    }
+    anomalies
+}
@@ -1,3 +1,21 @@
+pub mod cdc;
 pub mod db;
+mod error;
+pub mod history;
+pub mod lock;
+pub mod model;
+pub mod observe;
+pub mod parse;
+pub mod repl;
+pub mod resp;
+pub mod savepoint;
+pub mod scan;
+pub mod sim;
+mod ssi;
+pub mod stats;
 pub mod tx;
-mod utils;
\ No newline at end of file
+mod utils;
+pub mod vacuum;
+pub mod wal;
+
+pub use error::{DisplayKey, Error};
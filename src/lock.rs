@@ -0,0 +1,319 @@
+use crate::db::*;
+use crate::error::*;
+use crate::stats::*;
+use crate::tx::*;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Condvar, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+/// What a lock request does when another transaction holds a conflicting
+/// lock.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum WaitPolicy {
+    /// Wait until the lock is free.
+    #[default]
+    Block,
+    /// Fail with [`Error::LockConflict`] right away.
+    NoWait,
+    /// Wait at most this long, then fail with [`Error::LockTimeout`].
+    Timeout(Duration),
+}
+
+/// Which transaction of a deadlock is aborted to break it.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum DeadlockVictim {
+    /// The one that began last, which has likely done the least work.
+    #[default]
+    Youngest,
+    /// The one that wrote the fewest keys, the youngest of them on a tie.
+    FewestWrites,
+}
+
+/// A transaction waiting for a lock.
+struct Waiter<K> {
+    key: K,
+    mode: LockMode,
+    writes: usize,
+}
+
+struct LockTable<K> {
+    /// Every transaction holding a lock on a key, with the strongest mode it
+    /// holds.
+    holders: BTreeMap<K, BTreeMap<TxIdType, LockMode>>,
+    /// The keys each transaction holds locks on.
+    held: BTreeMap<TxIdType, BTreeSet<K>>,
+    /// The transactions waiting for a lock, which with the holders make up the
+    /// waits-for graph.
+    waiting: BTreeMap<TxIdType, Waiter<K>>,
+    /// Waiting transactions chosen to break a deadlock, which have not woken
+    /// up yet.
+    victims: BTreeSet<TxIdType>,
+}
+
+/// Shared and exclusive locks on keys, held until the transaction finishes.
+///
+/// Locks only conflict with other locks. Plain reads and writes never take or
+/// wait for them, so they serialize the transactions that ask for them, e.g.
+/// with [`Command::GetForUpdate`], and leave everything else to the
+/// optimistic checks at commit.
+pub struct LockManager<K = KeyType> {
+    table: Mutex<LockTable<K>>,
+    released: Condvar,
+}
+
+impl<K: Ord + Clone> Default for LockManager<K> {
+    fn default() -> Self {
+        LockManager {
+            table: Mutex::new(LockTable {
+                holders: BTreeMap::new(),
+                held: BTreeMap::new(),
+                waiting: BTreeMap::new(),
+                victims: BTreeSet::new(),
+            }),
+            released: Condvar::new(),
+        }
+    }
+}
+
+impl<K: Ord + Clone> LockManager<K> {
+    /// The transactions holding a lock on `key` that conflicts with `mode`.
+    fn blockers<'a>(
+        table: &'a LockTable<K>,
+        tx_id: TxIdType,
+        key: &K,
+        mode: LockMode,
+    ) -> impl Iterator<Item = TxIdType> + 'a {
+        table
+            .holders
+            .get(key)
+            .into_iter()
+            .flatten()
+            .filter(move |(holder, held)| {
+                **holder != tx_id && (mode == LockMode::Exclusive || **held == LockMode::Exclusive)
+            })
+            .map(|(holder, _)| *holder)
+    }
+
+    /// A cycle of the waits-for graph through `tx_id`, starting with it.
+    ///
+    /// Every wait is checked when it starts, so a new cycle always goes
+    /// through the transaction that just started waiting. Victims are left
+    /// out since they are about to give up.
+    fn cycle(table: &LockTable<K>, tx_id: TxIdType) -> Option<Vec<TxIdType>> {
+        let mut path = vec![tx_id];
+        let mut visited = BTreeSet::from([tx_id]);
+        let mut stack = vec![Self::waits_for(table, tx_id)];
+        while let Some(next) = stack.last_mut() {
+            let Some(blocker) = next.pop() else {
+                stack.pop();
+                path.pop();
+                continue;
+            };
+            if blocker == tx_id {
+                return Some(path);
+            }
+            if visited.insert(blocker) {
+                path.push(blocker);
+                stack.push(Self::waits_for(table, blocker));
+            }
+        }
+        None
+    }
+
+    fn waits_for(table: &LockTable<K>, tx_id: TxIdType) -> Vec<TxIdType> {
+        match table.waiting.get(&tx_id) {
+            Some(waiter) if !table.victims.contains(&tx_id) => {
+                Self::blockers(table, tx_id, &waiter.key, waiter.mode).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn victim(table: &LockTable<K>, cycle: &[TxIdType], policy: DeadlockVictim) -> TxIdType {
+        let youngest = cycle.iter().copied().max();
+        match policy {
+            DeadlockVictim::Youngest => youngest,
+            DeadlockVictim::FewestWrites => cycle
+                .iter()
+                .copied()
+                .min_by_key(|tx_id| (table.waiting[tx_id].writes, std::cmp::Reverse(*tx_id))),
+        }
+        .unwrap()
+    }
+
+    /// Locks `key` for `tx_id`, which has written `writes` keys so far. Asking
+    /// again for a lock already held is a no-op, and asking for an exclusive
+    /// lock while holding a shared one upgrades it.
+    ///
+    /// Before waiting, the waits-for graph is checked for a cycle. If there is
+    /// one, the transaction `victim` picks from it fails with
+    /// [`Error::Deadlock`], which may be this one or another waiter.
+    pub fn acquire(
+        &self,
+        tx_id: TxIdType,
+        key: &K,
+        mode: LockMode,
+        policy: WaitPolicy,
+        victim: DeadlockVictim,
+        writes: usize,
+    ) -> Result<(), Error<K>> {
+        let deadline = match policy {
+            WaitPolicy::Timeout(timeout) => Some(Instant::now() + timeout),
+            _ => None,
+        };
+        let mut table = self.table.lock().unwrap();
+        let waited = loop {
+            let Some(holder) = Self::blockers(&table, tx_id, key, mode).next() else {
+                break Ok(());
+            };
+            if policy == WaitPolicy::NoWait {
+                break Err(Error::LockConflict {
+                    key: key.clone(),
+                    holder,
+                });
+            }
+            if table.victims.remove(&tx_id) {
+                break Err(Error::Deadlock {
+                    key: key.clone(),
+                    tx_id,
+                });
+            }
+            table.waiting.insert(
+                tx_id,
+                Waiter {
+                    key: key.clone(),
+                    mode,
+                    writes,
+                },
+            );
+            if let Some(cycle) = Self::cycle(&table, tx_id) {
+                let victim = Self::victim(&table, &cycle, victim);
+                table.victims.insert(victim);
+                self.released.notify_all();
+                continue;
+            }
+            table = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break Err(Error::LockTimeout { key: key.clone() });
+                    }
+                    self.released.wait_timeout(table, deadline - now).unwrap().0
+                }
+                None => self.released.wait(table).unwrap(),
+            };
+        };
+        table.waiting.remove(&tx_id);
+        // Chosen too late, the lock is ours or the wait is over anyway.
+        table.victims.remove(&tx_id);
+        waited?;
+
+        let held = table
+            .holders
+            .entry(key.clone())
+            .or_default()
+            .entry(tx_id)
+            .or_insert(mode);
+        *held = (*held).max(mode);
+        table.held.entry(tx_id).or_default().insert(key.clone());
+        Ok(())
+    }
+
+    /// Releases every lock `tx_id` holds and wakes up whoever waits for them.
+    pub fn release_all(&self, tx_id: TxIdType) {
+        let mut table = self.table.lock().unwrap();
+        let Some(keys) = table.held.remove(&tx_id) else {
+            return;
+        };
+        for key in keys {
+            if let Some(holders) = table.holders.get_mut(&key) {
+                holders.remove(&tx_id);
+                if holders.is_empty() {
+                    table.holders.remove(&key);
+                }
+            }
+        }
+        self.released.notify_all();
+    }
+
+    /// The key each waiting transaction waits for.
+    pub fn waiting(&self) -> BTreeMap<TxIdType, K> {
+        let table = self.table.lock().unwrap();
+        table
+            .waiting
+            .iter()
+            .map(|(tx_id, waiter)| (*tx_id, waiter.key.clone()))
+            .collect()
+    }
+
+    /// The locks `tx_id` holds.
+    pub fn held(&self, tx_id: TxIdType) -> BTreeMap<K, LockMode> {
+        let table = self.table.lock().unwrap();
+        table
+            .held
+            .get(&tx_id)
+            .into_iter()
+            .flatten()
+            .map(|key| (key.clone(), table.holders[key][&tx_id]))
+            .collect()
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Database<K, V> {
+    /// Locks `key` for `tx`, which is aborted if it is picked to break a
+    /// deadlock, or if a transaction outside its snapshot already committed a
+    /// write to the key: the snapshot could only return a stale value, which
+    /// the lock would not make any safer.
+    pub(crate) fn lock_key(
+        &self,
+        tx: &Arc<RwLock<Transaction<K>>>,
+        key: &K,
+        mode: LockMode,
+    ) -> Result<(), Error<K>> {
+        let (tx_id, writes) = {
+            let tx = tx.read().unwrap();
+            (tx.id, tx.write_set.len())
+        };
+        let acquired = self.locks.acquire(
+            tx_id,
+            key,
+            mode,
+            self.lock_wait_policy,
+            self.deadlock_victim,
+            writes,
+        );
+        if let Err(Error::Deadlock { .. }) = acquired {
+            // The others in the cycle wait for our locks.
+            self.finish_transaction(tx_id, TransactionState::Aborted, AbortReason::Deadlock)?;
+        }
+        acquired?;
+
+        let conflict = {
+            let txs_info = self.txs_info.read().unwrap();
+            let tx = tx.read().unwrap();
+            match tx.isolation_level {
+                IsolationLevel::ReadUncommitted | IsolationLevel::ReadCommitted => None,
+                _ => Self::conflict_check(&txs_info, &tx, |_, other| {
+                    other.write_set.get(key).cloned()
+                }),
+            }
+        };
+        if let Some((key, other_tx)) = conflict {
+            self.finish_transaction(
+                tx_id,
+                TransactionState::Aborted,
+                AbortReason::WriteWriteConflict,
+            )?;
+            return Err(Error::WriteWriteConflict { key, other_tx });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use rrmvcc::db::*;
+use rrmvcc::repl::*;
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    process,
+};
+
+/// `rrmvcc [<wal path>]`: a REPL over an in-memory database, or a durable one
+/// if a write-ahead log is given. Type `\?` for help.
+fn main() {
+    let db = match env::args().nth(1) {
+        Some(path) => Database::open(&path).unwrap_or_else(|e| {
+            eprintln!("cannot open {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => Database::new(),
+    };
+    let mut repl = Repl::new(db);
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("{}", repl.prompt());
+        stdout.flush().unwrap();
+        let Some(Ok(line)) = lines.next() else {
+            println!();
+            return;
+        };
+        match repl.exec_line(&line) {
+            Some(output) if output.is_empty() => {}
+            Some(output) => println!("{}", output),
+            None => return,
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::db::*;
+use crate::error::*;
+use crate::scan::*;
+use crate::sim::*;
+use crate::tx::*;
+use std::{collections::BTreeMap, fmt, ops::Bound};
+
+/// The reference for a serializable database: a map that transactions are
+/// applied to one at a time.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Model<K = KeyType, V = ValueType> {
+    pub data: BTreeMap<K, V>,
+}
+
+impl<K: Prefix + Ord + Clone, V: Clone> Model<K, V> {
+    pub fn new(data: BTreeMap<K, V>) -> Self {
+        Model { data }
+    }
+
+    /// Runs the commands of transaction `tx_id` on their own, returning what
+    /// each would have responded. `Begin`, `Commit` and `Abort` are ignored,
+    /// so their responses are `None`.
+    pub fn run(
+        &mut self,
+        tx_id: TxIdType,
+        commands: &[Command<K, V>],
+    ) -> Vec<Option<Response<K, V>>> {
+        let mut savepoints: Vec<(String, BTreeMap<K, V>)> = Vec::new();
+        commands
+            .iter()
+            .map(|command| {
+                Some(match command.clone() {
+                    Command::Begin | Command::BeginWith(_) | Command::Commit | Command::Abort => {
+                        return None
+                    }
+                    Command::Get(key) | Command::GetForUpdate(key) | Command::GetForShare(key) => {
+                        let value = self.data.get(&key).cloned();
+                        Response::Value { tx_id, key, value }
+                    }
+                    Command::Set(key, value) => {
+                        self.data.insert(key.clone(), value.clone());
+                        Response::Written { tx_id, key, value }
+                    }
+                    Command::Delete(key) => {
+                        self.data.remove(&key);
+                        Response::Deleted { tx_id, key }
+                    }
+                    Command::Scan {
+                        start,
+                        end,
+                        limit,
+                        reverse,
+                    } => self.rows(tx_id, (start, end), limit, reverse),
+                    Command::ScanPrefix {
+                        prefix,
+                        limit,
+                        reverse,
+                    } => self.rows(tx_id, prefix.prefix_range(), limit, reverse),
+                    Command::Savepoint(name) => {
+                        savepoints.push((name.clone(), self.data.clone()));
+                        Response::SavepointSet { tx_id, name }
+                    }
+                    Command::RollbackTo(name) => {
+                        let i = savepoints.iter().rposition(|(n, _)| *n == name)?;
+                        savepoints.truncate(i + 1);
+                        self.data = savepoints[i].1.clone();
+                        Response::RolledBack { tx_id, name }
+                    }
+                    Command::Release(name) => {
+                        let i = savepoints.iter().rposition(|(n, _)| *n == name)?;
+                        savepoints.truncate(i);
+                        Response::Released { tx_id, name }
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn rows(
+        &self,
+        tx_id: TxIdType,
+        range: (Bound<K>, Bound<K>),
+        limit: Option<usize>,
+        reverse: bool,
+    ) -> Response<K, V> {
+        let empty = match &range {
+            (Bound::Unbounded, _) | (_, Bound::Unbounded) => false,
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (Bound::Included(start) | Bound::Excluded(start), Bound::Excluded(end))
+            | (Bound::Excluded(start), Bound::Included(end)) => start >= end,
+        };
+        if empty {
+            return Response::Rows {
+                tx_id,
+                rows: Vec::new(),
+            };
+        }
+        let limit = limit.unwrap_or(usize::MAX);
+        let rows = self.data.range(range).map(|(k, v)| (k.clone(), v.clone()));
+        let rows = if reverse {
+            rows.rev().take(limit).collect()
+        } else {
+            rows.take(limit).collect()
+        };
+        Response::Rows { tx_id, rows }
+    }
+}
+
+/// A committed transaction of a run, with the commands that succeeded and
+/// their responses.
+struct Committed<K, V> {
+    tx_id: TxIdType,
+    commands: Vec<Command<K, V>>,
+    responses: Vec<Response<K, V>>,
+}
+
+/// The committed transactions of `run`, in commit order.
+fn committed<K: Clone, V: Clone>(run: &Run<K, V>) -> Vec<Committed<K, V>> {
+    let mut active: BTreeMap<usize, Committed<K, V>> = BTreeMap::new();
+    let mut committed = Vec::new();
+    for (step, result) in run.steps.iter().zip(&run.results) {
+        match (&step.command, result) {
+            (_, Ok(Response::Begun { tx_id })) => {
+                active.insert(
+                    step.connection,
+                    Committed {
+                        tx_id: *tx_id,
+                        commands: Vec::new(),
+                        responses: Vec::new(),
+                    },
+                );
+            }
+            (_, Ok(Response::Committed { .. })) => {
+                committed.extend(active.remove(&step.connection));
+            }
+            (Command::Commit | Command::Abort, _) => {
+                active.remove(&step.connection);
+            }
+            (command, Ok(response)) => {
+                if let Some(tx) = active.get_mut(&step.connection) {
+                    tx.commands.push(command.clone());
+                    tx.responses.push(response.clone());
+                }
+            }
+            (_, Err(_)) => {}
+        }
+    }
+    committed
+}
+
+/// Runs `txs` in turn on a model starting from `initial`, failing at the
+/// first response that differs from the run's or if the data ends up
+/// different from `data`.
+fn replay<K: Prefix + Ord + Clone + fmt::Debug, V: Clone + PartialEq + fmt::Debug>(
+    initial: &BTreeMap<K, V>,
+    txs: &[&Committed<K, V>],
+    data: &BTreeMap<K, V>,
+) -> Result<(), String> {
+    let mut model = Model::new(initial.clone());
+    for tx in txs {
+        let expected = model.run(tx.tx_id, &tx.commands);
+        for (response, expected) in tx.responses.iter().zip(expected) {
+            if Some(response) != expected.as_ref() {
+                return Err(format!("got {:?}, serially {:?}", response, expected));
+            }
+        }
+    }
+    if model.data != *data {
+        return Err(format!("ended with {:?}, serially {:?}", data, model.data));
+    }
+    Ok(())
+}
+
+/// Every ordering of `0..n`, starting with `0..n` itself.
+fn orders(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![Vec::new()];
+    }
+    let mut all = Vec::new();
+    for order in orders(n - 1) {
+        for i in (0..=order.len()).rev() {
+            let mut order = order.clone();
+            order.insert(i, n - 1);
+            all.push(order);
+        }
+    }
+    all
+}
+
+/// An oracle for [`Simulation`] runs that start from `initial`: the reads,
+/// scans and writes of the committed transactions, and the data left
+/// behind, have to be what some serial order of the same transactions gives.
+/// Commit order is tried first, then every other order, so keep the number
+/// of committed transactions small.
+pub fn serial<K, V>(initial: BTreeMap<K, V>) -> impl Fn(&Run<K, V>) -> Result<(), String>
+where
+    K: Prefix + Ord + Clone + fmt::Debug,
+    V: Clone + PartialEq + fmt::Debug,
+{
+    move |run| {
+        let txs = committed(run);
+        let mut c = run.db.new_connection();
+        let scanned = c
+            .exec_command(Command::BeginWith(BeginOptions {
+                isolation: Some(IsolationLevel::Snapshot),
+                ..Default::default()
+            }))
+            .and_then(|_| c.scan(Bound::Unbounded, Bound::Unbounded, false));
+        let data: BTreeMap<K, V> = scanned.map_err(|e: Error<K>| format!("{:?}", e))?.collect();
+        let mut first = None;
+        for order in orders(txs.len()) {
+            let ordered: Vec<_> = order.iter().map(|&i| &txs[i]).collect();
+            match replay(&initial, &ordered, &data) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    first.get_or_insert(e);
+                }
+            }
+        }
+        let tx_ids: Vec<_> = txs.iter().map(|tx| tx.tx_id).collect();
+        Err(format!(
+            "no serial order of transactions {:?} matches, in commit order {}",
+            tx_ids,
+            first.unwrap_or_default()
+        ))
+    }
+}
@@ -0,0 +1,60 @@
+use crate::db::*;
+use crate::error::*;
+use crate::tx::*;
+
+/// Something the engine did, as reported to an [`Observer`].
+#[derive(PartialEq, Clone, Debug)]
+pub enum Event<K = KeyType, V = ValueType> {
+    /// A transaction began.
+    Begin {
+        tx_id: TxIdType,
+        isolation: IsolationLevel,
+    },
+    /// A connection ran a command, in the transaction it had afterwards.
+    Command {
+        tx_id: Option<TxIdType>,
+        command: Command<K, V>,
+        outcome: Result<Response<K, V>, Error<K>>,
+    },
+    /// Whether a version is visible to a transaction, one event for each
+    /// version of the key a [`Command::Get`] looked at, matching the version
+    /// it read. `key` is `None` when the version was checked on its own with
+    /// [`Database::is_visible`]. Scans do not report the versions they skip.
+    Visibility {
+        tx_id: TxIdType,
+        key: Option<K>,
+        tx_start_id: TxIdType,
+        tx_end_id: TxIdType,
+        visible: bool,
+    },
+    /// A transaction was asked to finish in `state`. Failing to commit aborts
+    /// it.
+    Complete {
+        tx_id: TxIdType,
+        state: TransactionState,
+        outcome: Result<(), Error<K>>,
+    },
+}
+
+/// Receives every [`Event`] of a database, see [`Database::observer`].
+///
+/// Events are emitted once the engine released its locks, so an observer may
+/// use the database, and are delivered on the thread that caused them.
+pub trait Observer<K = KeyType, V = ValueType>: Send + Sync {
+    fn event(&self, event: &Event<K, V>);
+}
+
+impl<K, V, F: Fn(&Event<K, V>) + Send + Sync> Observer<K, V> for F {
+    fn event(&self, event: &Event<K, V>) {
+        self(event)
+    }
+}
+
+impl<K, V> Database<K, V> {
+    /// Builds `event` and hands it to the observer, if there is one.
+    pub(crate) fn emit(&self, event: impl FnOnce() -> Event<K, V>) {
+        if let Some(observer) = &self.observer {
+            observer.event(&event());
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use crate::db::*;
+use crate::tx::*;
+use std::{fmt, ops::Bound, str::FromStr};
+
+/// A line that is not a valid command.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ParseError(pub String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn error<T>(message: impl Into<String>) -> Result<T, ParseError> {
+    Err(ParseError(message.into()))
+}
+
+/// The words of a line, read one at a time.
+struct Words<'a>(&'a str);
+
+impl<'a> Words<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let line = self.0.trim_start();
+        if line.is_empty() {
+            return None;
+        }
+        let end = line.find(char::is_whitespace).unwrap_or(line.len());
+        self.0 = &line[end..];
+        Some(&line[..end])
+    }
+
+    /// Consumes the next word if it is `keyword`, in any case.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let mut peek = Words(self.0);
+        match peek.next() {
+            Some(word) if word.eq_ignore_ascii_case(keyword) => {
+                self.0 = peek.0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, what: &str) -> Result<&'a str, ParseError> {
+        match self.next() {
+            Some(word) => Ok(word),
+            None => error(format!("expected {}", what)),
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, what: &str) -> Result<T, ParseError> {
+        let word = self.expect(what)?;
+        word.parse()
+            .or_else(|_| error(format!("invalid {} {}", what, word)))
+    }
+
+    /// Everything left, with surrounding whitespace trimmed.
+    fn rest(&mut self, what: &str) -> Result<&'a str, ParseError> {
+        let rest = std::mem::take(&mut self.0).trim();
+        if rest.is_empty() {
+            return error(format!("expected {}", what));
+        }
+        Ok(rest)
+    }
+
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.next() {
+            Some(word) => error(format!("unexpected {}", word)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Accepts the level in any case, with or without `_` or `-` between words,
+/// e.g. `snapshot`, `read_committed` or `REPEATABLE-READ`.
+impl FromStr for IsolationLevel {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .chars()
+            .filter(|c| *c != '_' && *c != '-')
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "readuncommitted" => Ok(IsolationLevel::ReadUncommitted),
+            "readcommitted" => Ok(IsolationLevel::ReadCommitted),
+            "repeatableread" => Ok(IsolationLevel::RepeatableRead),
+            "snapshot" => Ok(IsolationLevel::Snapshot),
+            "serializable" => Ok(IsolationLevel::Serializable),
+            _ => error(format!("unknown isolation level {}", s)),
+        }
+    }
+}
+
+/// Parses one command per line. Keywords are case-insensitive, keys are single
+/// words and a value is the rest of the line:
+///
+/// ```text
+/// BEGIN [ISOLATION <level>] [READ ONLY] [AS OF <commit ts>]
+/// GET <key> [FOR UPDATE | FOR SHARE]
+/// SET <key> <value>
+/// DELETE <key>
+/// SCAN [<start> [<end>]] [LIMIT <n>] [REVERSE]
+/// PREFIX <prefix> [LIMIT <n>] [REVERSE]
+/// SAVEPOINT <name>
+/// ROLLBACK TO <name>
+/// RELEASE <name>
+/// COMMIT
+/// ABORT
+/// ```
+///
+/// `SCAN` includes `start` and stops before `end`. `DEL` and `ROLLBACK` are
+/// accepted for `DELETE` and `ABORT`.
+impl<K: FromStr, V: FromStr> FromStr for Command<K, V> {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = Words(line);
+        let name = words.expect("a command")?;
+        let command = match name.to_ascii_uppercase().as_str() {
+            "BEGIN" => parse_begin(&mut words)?,
+            "GET" => {
+                let key = words.parse("key")?;
+                if words.keyword("FOR") {
+                    if words.keyword("UPDATE") {
+                        Command::GetForUpdate(key)
+                    } else if words.keyword("SHARE") {
+                        Command::GetForShare(key)
+                    } else {
+                        return error("expected UPDATE or SHARE");
+                    }
+                } else {
+                    Command::Get(key)
+                }
+            }
+            "SET" => {
+                let key = words.parse("key")?;
+                let value = words.rest("value")?;
+                let value = value
+                    .parse()
+                    .or_else(|_| error(format!("invalid value {}", value)))?;
+                Command::Set(key, value)
+            }
+            "DELETE" | "DEL" => Command::Delete(words.parse("key")?),
+            "SCAN" => {
+                let mut bounds = Vec::new();
+                while bounds.len() < 2 {
+                    let mut peek = Words(words.0);
+                    match peek.next() {
+                        Some(word)
+                            if !word.eq_ignore_ascii_case("LIMIT")
+                                && !word.eq_ignore_ascii_case("REVERSE") =>
+                        {
+                            bounds.push(words.parse("key")?);
+                        }
+                        _ => break,
+                    }
+                }
+                let mut bounds = bounds.into_iter();
+                let start = bounds.next().map_or(Bound::Unbounded, Bound::Included);
+                let end = bounds.next().map_or(Bound::Unbounded, Bound::Excluded);
+                let (limit, reverse) = parse_scan_options(&mut words)?;
+                Command::Scan {
+                    start,
+                    end,
+                    limit,
+                    reverse,
+                }
+            }
+            "PREFIX" => {
+                let prefix = words.parse("prefix")?;
+                let (limit, reverse) = parse_scan_options(&mut words)?;
+                Command::ScanPrefix {
+                    prefix,
+                    limit,
+                    reverse,
+                }
+            }
+            "SAVEPOINT" => Command::Savepoint(words.expect("savepoint name")?.to_string()),
+            "ROLLBACK" if words.keyword("TO") => {
+                words.keyword("SAVEPOINT");
+                Command::RollbackTo(words.expect("savepoint name")?.to_string())
+            }
+            "RELEASE" => {
+                words.keyword("SAVEPOINT");
+                Command::Release(words.expect("savepoint name")?.to_string())
+            }
+            "COMMIT" => Command::Commit,
+            "ABORT" | "ROLLBACK" => Command::Abort,
+            _ => return error(format!("unknown command {}", name)),
+        };
+        words.end()?;
+        Ok(command)
+    }
+}
+
+fn parse_begin<K, V>(words: &mut Words) -> Result<Command<K, V>, ParseError> {
+    let mut options = BeginOptions::default();
+    loop {
+        if words.keyword("ISOLATION") {
+            words.keyword("LEVEL");
+            options.isolation = Some(words.expect("isolation level")?.parse()?);
+        } else if words.keyword("READ") {
+            if !words.keyword("ONLY") {
+                return error("expected ONLY");
+            }
+            options.read_only = true;
+        } else if words.keyword("AS") {
+            if !words.keyword("OF") {
+                return error("expected OF");
+            }
+            options.as_of = Some(words.parse("commit timestamp")?);
+        } else {
+            break;
+        }
+    }
+    if options == BeginOptions::default() {
+        Ok(Command::Begin)
+    } else {
+        Ok(Command::BeginWith(options))
+    }
+}
+
+fn parse_scan_options(words: &mut Words) -> Result<(Option<usize>, bool), ParseError> {
+    let mut limit = None;
+    let mut reverse = false;
+    loop {
+        if words.keyword("LIMIT") {
+            limit = Some(words.parse("limit")?);
+        } else if words.keyword("REVERSE") {
+            reverse = true;
+        } else {
+            return Ok((limit, reverse));
+        }
+    }
+}
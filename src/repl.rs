@@ -0,0 +1,104 @@
+use crate::db::*;
+use crate::lock::*;
+use crate::tx::*;
+use std::collections::BTreeMap;
+
+const HELP: &str = "\\c <name>  open or switch to a connection
+\\l         list connections
+\\q         quit
+\\?         show this help
+Commands: BEGIN [ISOLATION <level>] [READ ONLY] [AS OF <ts>], GET <key> [FOR UPDATE | FOR SHARE],
+SET <key> <value>, DELETE <key>, SCAN [<start> [<end>]] [LIMIT <n>] [REVERSE],
+PREFIX <prefix> [LIMIT <n>] [REVERSE], SAVEPOINT <name>, ROLLBACK TO <name>,
+RELEASE <name>, COMMIT, ABORT";
+
+/// Named connections to one database, driven a line at a time, so anomalies
+/// can be replayed by hand by switching between them.
+pub struct Repl {
+    db: Database,
+    connections: BTreeMap<String, Connection>,
+    current: String,
+}
+
+impl Repl {
+    /// Starts with a single connection called `c1`.
+    ///
+    /// Every connection runs on the caller's thread, where waiting for a lock
+    /// held by another one would never end. So the database's
+    /// `lock_wait_policy` is set to [`WaitPolicy::NoWait`], and a lock
+    /// conflict fails the command at once.
+    pub fn new(mut db: Database) -> Self {
+        db.lock_wait_policy = WaitPolicy::NoWait;
+        let mut repl = Repl {
+            db,
+            connections: BTreeMap::new(),
+            current: String::new(),
+        };
+        repl.switch("c1");
+        repl
+    }
+
+    pub fn prompt(&self) -> String {
+        format!("{}> ", self.current)
+    }
+
+    fn switch(&mut self, name: &str) {
+        if !self.connections.contains_key(name) {
+            self.connections
+                .insert(name.to_string(), self.db.new_connection());
+        }
+        self.current = name.to_string();
+    }
+
+    /// Runs a command or a `\` meta command and returns what to print, or
+    /// `None` once asked to quit.
+    pub fn exec_line(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Some(String::new());
+        }
+        if let Some(meta) = line.strip_prefix('\\') {
+            let mut words = meta.split_whitespace();
+            return match (words.next(), words.next(), words.next()) {
+                (Some("q"), None, _) => None,
+                (Some("c"), Some(name), None) => {
+                    self.switch(name);
+                    Some(format!("connected to {}", name))
+                }
+                (Some("l"), None, _) => Some(self.list()),
+                (Some("?"), None, _) => Some(HELP.to_string()),
+                _ => Some(format!("ERROR: unknown meta command \\{}", meta)),
+            };
+        }
+
+        let command: Command = match line.parse() {
+            Ok(command) => command,
+            Err(e) => return Some(format!("ERROR: {}", e)),
+        };
+        let connection = self.connections.get_mut(&self.current).unwrap();
+        Some(match connection.exec_command(command) {
+            Ok(response) => response.to_string(),
+            Err(e) => format!("ERROR: {}", e),
+        })
+    }
+
+    fn list(&self) -> String {
+        self.connections
+            .iter()
+            .map(|(name, connection)| {
+                let current = if *name == self.current { "*" } else { " " };
+                match &connection.tx {
+                    Some(tx) => {
+                        let tx = tx.read().unwrap();
+                        format!(
+                            "{} {} tx {} {:?} {:?}",
+                            current, name, tx.id, tx.isolation_level, tx.state
+                        )
+                    }
+                    None => format!("{} {} no transaction", current, name),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
@@ -0,0 +1,391 @@
+use crate::db::*;
+use crate::error::*;
+use crate::tx::*;
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+/// A RESP reply.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    /// `None` is the nil bulk string.
+    Bulk(Option<String>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn ok() -> Self {
+        Reply::Simple("OK".to_string())
+    }
+
+    /// Retryable errors get their own `CONFLICT` prefix so clients can tell
+    /// when to run the transaction again.
+    fn from_error(e: &Error) -> Self {
+        let kind = if e.is_retryable() { "CONFLICT" } else { "ERR" };
+        Reply::Error(format!("{} {}", kind, e))
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Reply::Simple(s) => buf.extend_from_slice(format!("+{}\r\n", s).as_bytes()),
+            Reply::Error(e) => buf.extend_from_slice(format!("-{}\r\n", e).as_bytes()),
+            Reply::Integer(n) => buf.extend_from_slice(format!(":{}\r\n", n).as_bytes()),
+            Reply::Bulk(None) => buf.extend_from_slice(b"$-1\r\n"),
+            Reply::Bulk(Some(s)) => {
+                buf.extend_from_slice(format!("${}\r\n{}\r\n", s.len(), s).as_bytes())
+            }
+            Reply::Array(replies) => {
+                buf.extend_from_slice(format!("*{}\r\n", replies.len()).as_bytes());
+                for reply in replies {
+                    reply.encode(buf);
+                }
+            }
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.take(MAX_LINE as u64 + 1).read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.len() > MAX_LINE {
+        return Err(invalid("line too long"));
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// The longest a line may be with its line ending, whether an inline command
+/// or the length of an array or bulk string, as in Redis.
+pub const MAX_LINE: usize = 64 * 1024;
+/// The most arguments a request may have.
+pub const MAX_ARGS: usize = 1024 * 1024;
+/// The longest a bulk string may be, as in Redis.
+pub const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// Reads a request, either an array of bulk strings or an inline command as
+/// typed into telnet. `None` once the client hung up.
+///
+/// Lines over [`MAX_LINE`] are rejected once that much was read, lengths
+/// over [`MAX_ARGS`] and [`MAX_BULK_LEN`] before anything is allocated for
+/// them, and a bulk string is only buffered as it arrives.
+pub fn read_request(reader: &mut impl BufRead) -> io::Result<Option<Vec<String>>> {
+    let Some(line) = read_line(reader)? else {
+        return Ok(None);
+    };
+    let Some(len) = line.strip_prefix('*') else {
+        return Ok(Some(line.split_whitespace().map(str::to_string).collect()));
+    };
+    let len: usize = len.parse().map_err(|_| invalid("invalid array length"))?;
+    if len > MAX_ARGS {
+        return Err(invalid("too many arguments"));
+    }
+    let mut args = Vec::new();
+    for _ in 0..len {
+        let line = read_line(reader)?.ok_or_else(|| invalid("unexpected end of request"))?;
+        let len: usize = line
+            .strip_prefix('$')
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| invalid("expected a bulk string"))?;
+        if len > MAX_BULK_LEN {
+            return Err(invalid("bulk string too long"));
+        }
+        let mut data = Vec::new();
+        reader.take(len as u64 + 2).read_to_end(&mut data)?;
+        if data.len() < len + 2 {
+            return Err(invalid("unexpected end of request"));
+        }
+        data.truncate(len);
+        args.push(String::from_utf8(data).map_err(|_| invalid("arguments must be UTF-8"))?);
+    }
+    Ok(Some(args))
+}
+
+/// A command that touches data, run in a transaction of its own unless one
+/// was started with `BEGIN` or `MULTI`.
+enum Op {
+    Get(String),
+    Set(String, String),
+    Del(Vec<String>),
+}
+
+impl Op {
+    fn parse(name: &str, args: &[String]) -> Option<Self> {
+        match (name, args) {
+            ("GET", [key]) => Some(Op::Get(key.clone())),
+            ("SET", [key, value]) => Some(Op::Set(key.clone(), value.clone())),
+            ("DEL", keys) if !keys.is_empty() => Some(Op::Del(keys.to_vec())),
+            _ => None,
+        }
+    }
+
+    fn run(self, connection: &mut Connection) -> Result<Reply, Error> {
+        match self {
+            Op::Get(key) => match connection.exec_command(Command::Get(key))? {
+                Response::Value { value, .. } => Ok(Reply::Bulk(value)),
+                _ => unreachable!(),
+            },
+            Op::Set(key, value) => {
+                connection.exec_command(Command::Set(key, value))?;
+                Ok(Reply::ok())
+            }
+            Op::Del(keys) => {
+                let mut deleted = 0;
+                for key in keys {
+                    if let Response::Value { value: None, .. } =
+                        connection.exec_command(Command::Get(key.clone()))?
+                    {
+                        continue;
+                    }
+                    connection.exec_command(Command::Delete(key))?;
+                    deleted += 1;
+                }
+                Ok(Reply::Integer(deleted))
+            }
+        }
+    }
+}
+
+/// The state of one client: its connection, whether it is inside `BEGIN`,
+/// and the commands queued since `MULTI`.
+pub struct Session {
+    connection: Connection,
+    explicit: bool,
+    queued: Option<Vec<Op>>,
+}
+
+impl Session {
+    pub fn new(db: &Database) -> Self {
+        Session {
+            connection: db.new_connection(),
+            explicit: false,
+            queued: None,
+        }
+    }
+
+    fn in_transaction(&self) -> bool {
+        self.connection.active_transaction().is_ok()
+    }
+
+    fn begin(&mut self, options: BeginOptions) -> Result<(), Error> {
+        self.connection.exec_command(Command::BeginWith(options))?;
+        Ok(())
+    }
+
+    /// Commits the transaction the commands ran in, or aborts it if one
+    /// failed. An aborted transaction is left as it is.
+    fn finish(&mut self, ok: bool) -> Result<(), Error> {
+        if !self.in_transaction() {
+            return Ok(());
+        }
+        let command = if ok { Command::Commit } else { Command::Abort };
+        self.connection.exec_command(command)?;
+        Ok(())
+    }
+
+    fn autocommit(&mut self, op: Op) -> Reply {
+        if self.explicit {
+            let reply = op.run(&mut self.connection);
+            // A failure that aborted the transaction also ends the `BEGIN`.
+            self.explicit = self.in_transaction();
+            return reply.unwrap_or_else(|e| Reply::from_error(&e));
+        }
+        if let Err(e) = self.begin(BeginOptions::default()) {
+            return Reply::from_error(&e);
+        }
+        let reply = op.run(&mut self.connection);
+        let finished = self.finish(reply.is_ok());
+        match (reply, finished) {
+            (Err(e), _) | (Ok(_), Err(e)) => Reply::from_error(&e),
+            (Ok(reply), Ok(())) => reply,
+        }
+    }
+
+    /// Runs the queued commands in one transaction. If one fails, the
+    /// transaction is aborted, even one started with `BEGIN`, and the reply is
+    /// that error.
+    fn exec(&mut self, ops: Vec<Op>) -> Reply {
+        if !self.explicit {
+            if let Err(e) = self.begin(BeginOptions::default()) {
+                return Reply::from_error(&e);
+            }
+        }
+        let replies: Result<Vec<_>, _> = ops
+            .into_iter()
+            .map(|op| op.run(&mut self.connection))
+            .collect();
+        let finished = match (&replies, self.explicit) {
+            (Ok(_), true) => Ok(()),
+            (Ok(_), false) => self.finish(true),
+            (Err(_), _) => {
+                self.explicit = false;
+                self.finish(false)
+            }
+        };
+        match (replies, finished) {
+            (Err(e), _) | (Ok(_), Err(e)) => Reply::from_error(&e),
+            (Ok(replies), Ok(())) => Reply::Array(replies),
+        }
+    }
+
+    /// Runs a request and returns the reply.
+    ///
+    /// Besides `GET`, `SET` and `DEL`, `MULTI` queues commands until `EXEC`
+    /// runs them in one transaction or `DISCARD` drops them. `BEGIN
+    /// [ISOLATION <level>]` starts a transaction that spans requests until
+    /// `COMMIT`, `ROLLBACK` or a failure that aborts it, after which commands
+    /// run on their own again.
+    pub fn handle(&mut self, args: &[String]) -> Reply {
+        let Some((name, args)) = args.split_first() else {
+            return Reply::Error("ERR empty request".to_string());
+        };
+        let name = name.to_ascii_uppercase();
+        if let Some(queued) = &mut self.queued {
+            match name.as_str() {
+                "EXEC" if args.is_empty() => {
+                    let ops = self.queued.take().unwrap();
+                    return self.exec(ops);
+                }
+                "DISCARD" if args.is_empty() => {
+                    self.queued = None;
+                    return Reply::ok();
+                }
+                "MULTI" => return Reply::Error("ERR MULTI calls can not be nested".to_string()),
+                _ => {}
+            }
+            return match Op::parse(&name, args) {
+                Some(op) => {
+                    queued.push(op);
+                    Reply::Simple("QUEUED".to_string())
+                }
+                None => Reply::Error(format!("ERR cannot queue {}", name)),
+            };
+        }
+
+        match (name.as_str(), args) {
+            ("PING", []) => Reply::Simple("PONG".to_string()),
+            ("MULTI", []) => {
+                self.queued = Some(Vec::new());
+                Reply::ok()
+            }
+            ("EXEC" | "DISCARD", []) => Reply::Error(format!("ERR {} without MULTI", name)),
+            ("BEGIN", args) => {
+                let isolation = match args {
+                    [] => None,
+                    [keyword, level] if keyword.eq_ignore_ascii_case("ISOLATION") => {
+                        match level.parse() {
+                            Ok(level) => Some(level),
+                            Err(e) => return Reply::Error(format!("ERR {}", e)),
+                        }
+                    }
+                    _ => return Reply::Error("ERR usage: BEGIN [ISOLATION <level>]".to_string()),
+                };
+                if self.explicit && self.in_transaction() {
+                    return Reply::Error("ERR transaction already started".to_string());
+                }
+                match self.begin(BeginOptions {
+                    isolation,
+                    ..Default::default()
+                }) {
+                    Ok(()) => {
+                        self.explicit = true;
+                        Reply::ok()
+                    }
+                    Err(e) => Reply::from_error(&e),
+                }
+            }
+            ("COMMIT", []) => {
+                self.explicit = false;
+                match self.connection.exec_command(Command::Commit) {
+                    Ok(_) => Reply::ok(),
+                    Err(e) => Reply::from_error(&e),
+                }
+            }
+            ("ROLLBACK", []) => {
+                self.explicit = false;
+                match self.finish(false) {
+                    Ok(()) => Reply::ok(),
+                    Err(e) => Reply::from_error(&e),
+                }
+            }
+            _ => match Op::parse(&name, args) {
+                Some(op) => self.autocommit(op),
+                None => Reply::Error(format!(
+                    "ERR unknown command or wrong number of arguments for {}",
+                    name
+                )),
+            },
+        }
+    }
+}
+
+/// Serves one client until it hangs up or sends `QUIT`. A transaction it
+/// leaves open is aborted. A malformed request gets an error reply, then the
+/// client is disconnected.
+pub fn handle_client(stream: TcpStream, db: &Database) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut session = Session::new(db);
+    let result = (|| {
+        loop {
+            let args = match read_request(&mut reader) {
+                Ok(Some(args)) => args,
+                Ok(None) => break,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    let mut buf = Vec::new();
+                    Reply::Error(format!("ERR Protocol error: {}", e)).encode(&mut buf);
+                    writer.write_all(&buf)?;
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            };
+            if args.is_empty() {
+                continue;
+            }
+            let quit = args[0].eq_ignore_ascii_case("QUIT");
+            let reply = if quit {
+                Reply::ok()
+            } else {
+                session.handle(&args)
+            };
+            let mut buf = Vec::new();
+            reply.encode(&mut buf);
+            writer.write_all(&buf)?;
+            if quit {
+                break;
+            }
+        }
+        Ok(())
+    })();
+    let _ = session.finish(false);
+    result
+}
+
+/// Accepts clients on `listener`, each served on its own thread with its own
+/// [`Connection`], for as long as the process runs. A client that could not
+/// be accepted, for instance because the process ran out of file
+/// descriptors, is reported on stderr and the next one is waited for.
+pub fn serve(listener: TcpListener, db: Database) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("cannot accept a client: {}", e);
+                continue;
+            }
+        };
+        let db = db.clone();
+        thread::spawn(move || {
+            let _ = handle_client(stream, &db);
+        });
+    }
+}
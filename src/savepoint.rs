@@ -0,0 +1,188 @@
+use crate::db::*;
+use crate::error::*;
+use crate::tx::*;
+use crate::wal::*;
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, RwLock},
+};
+
+/// What a single write did to the version chain of `key`, so it can be
+/// undone by [`Command::RollbackTo`].
+#[derive(Debug)]
+pub struct Undo<K = KeyType> {
+    key: K,
+    /// Whether a new version was pushed.
+    pushed: bool,
+    /// The versions whose `tx_end_id` was stamped, with the stamp they had
+    /// before. A version is named by its position among the versions this
+    /// transaction ended. Vacuum keeps those while the transaction is active,
+    /// so the position stays valid while other versions come and go.
+    ended: Vec<(usize, TxIdType)>,
+}
+
+/// The state of a transaction when a savepoint was set.
+#[derive(Debug)]
+pub struct Savepoint<K = KeyType> {
+    pub name: String,
+    undo_len: usize,
+    write_set: BTreeSet<K>,
+    read_set: BTreeSet<K>,
+    read_ranges_len: usize,
+    ended_len: usize,
+}
+
+impl<K: Ord + Clone, V: Clone> Database<K, V> {
+    /// Ends every version of `key` visible to `tx`, returning what is needed
+    /// to undo it.
+    ///
+    /// A version whose end was already committed by another transaction, which
+    /// `tx` can still see in its snapshot, keeps that end. `tx` stops seeing it
+    /// anyway, as it only reads its own versions of a key it wrote.
+    pub(crate) fn end_visible(
+        txs_info: &TxInfo<K>,
+        tx: &mut Transaction<K>,
+        key: &K,
+        values: &mut [Value<V>],
+    ) -> Vec<(usize, TxIdType)> {
+        let mut stamped = Vec::new();
+        for (i, v) in values.iter_mut().enumerate() {
+            if v.tx_end_id != tx.id
+                && Self::visible(txs_info, tx, v)
+                && (v.tx_end_id == 0
+                    || txs_info.get_transaction_state(v.tx_end_id)
+                        != Some(TransactionState::Committed))
+            {
+                stamped.push((i, v.tx_end_id));
+                v.tx_end_id = tx.id;
+                tx.ended.push((key.clone(), v.tx_start_id));
+            }
+        }
+        let ours = Self::ended_by(tx, values);
+        stamped
+            .into_iter()
+            .map(|(i, prev)| (ours.binary_search(&i).unwrap(), prev))
+            .collect()
+    }
+
+    fn ended_by(tx: &Transaction<K>, values: &[Value<V>]) -> Vec<usize> {
+        values
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.tx_end_id == tx.id)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Remembers a write for a later rollback. Nothing is kept unless a
+    /// savepoint is set.
+    pub(crate) fn record_undo(
+        tx: &mut Transaction<K>,
+        key: K,
+        pushed: bool,
+        ended: Vec<(usize, TxIdType)>,
+    ) {
+        if !tx.savepoints.is_empty() {
+            tx.undo.push(Undo { key, pushed, ended });
+        }
+    }
+
+    pub(crate) fn savepoint(
+        &self,
+        tx: &Arc<RwLock<Transaction<K>>>,
+        name: String,
+    ) -> Result<(), Error<K>> {
+        let mut tx = tx.write().unwrap();
+        self.log(Record::Savepoint {
+            tx_id: tx.id,
+            name: name.clone(),
+        })?;
+        let savepoint = Savepoint {
+            name,
+            undo_len: tx.undo.len(),
+            write_set: tx.write_set.clone(),
+            read_set: tx.read_set.clone(),
+            read_ranges_len: tx.read_ranges.len(),
+            ended_len: tx.ended.len(),
+        };
+        tx.savepoints.push(savepoint);
+        Ok(())
+    }
+
+    /// The newest savepoint called `name`.
+    fn find_savepoint(tx: &Transaction<K>, name: &str) -> Result<usize, Error<K>> {
+        tx.savepoints
+            .iter()
+            .rposition(|savepoint| savepoint.name == name)
+            .ok_or_else(|| Error::SavepointNotFound {
+                tx_id: tx.id,
+                name: name.to_string(),
+            })
+    }
+
+    /// Undoes everything written since the savepoint `name`, which is kept,
+    /// and forgets the savepoints set after it.
+    pub(crate) fn rollback_to(
+        &self,
+        tx: &Arc<RwLock<Transaction<K>>>,
+        name: String,
+    ) -> Result<(), Error<K>> {
+        let mut kvlist = self.kvs_info.write().unwrap();
+        let mut tx = tx.write().unwrap();
+        let index = Self::find_savepoint(&tx, &name)?;
+        self.log(Record::RollbackTo { tx_id: tx.id, name })?;
+
+        tx.savepoints.truncate(index + 1);
+        let undo_len = tx.savepoints[index].undo_len;
+        let undo: Vec<_> = tx.undo.drain(undo_len..).rev().collect();
+        for undo in undo {
+            let values = kvlist
+                .get_mut(&undo.key)
+                .expect("undo for a key without versions");
+            if undo.pushed {
+                // The only version of ours we have not ended ourselves.
+                if let Some(i) = values
+                    .iter()
+                    .rposition(|v| v.tx_start_id == tx.id && v.tx_end_id != tx.id)
+                {
+                    values.remove(i);
+                }
+            }
+            let ours = Self::ended_by(&tx, values);
+            for (ordinal, prev) in undo.ended {
+                if let Some(&i) = ours.get(ordinal) {
+                    values[i].tx_end_id = prev;
+                }
+            }
+            if values.is_empty() {
+                kvlist.remove(&undo.key);
+            }
+        }
+
+        let savepoint = &tx.savepoints[index];
+        let (write_set, read_set) = (savepoint.write_set.clone(), savepoint.read_set.clone());
+        let (read_ranges_len, ended_len) = (savepoint.read_ranges_len, savepoint.ended_len);
+        tx.write_set = write_set;
+        tx.read_set = read_set;
+        tx.read_ranges.truncate(read_ranges_len);
+        tx.ended.truncate(ended_len);
+        Ok(())
+    }
+
+    /// Forgets the savepoint `name` and every one set after it, keeping what
+    /// was written since.
+    pub(crate) fn release(
+        &self,
+        tx: &Arc<RwLock<Transaction<K>>>,
+        name: String,
+    ) -> Result<(), Error<K>> {
+        let mut tx = tx.write().unwrap();
+        let index = Self::find_savepoint(&tx, &name)?;
+        self.log(Record::Release { tx_id: tx.id, name })?;
+        tx.savepoints.truncate(index);
+        if tx.savepoints.is_empty() {
+            tx.undo.clear();
+        }
+        Ok(())
+    }
+}
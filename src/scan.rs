@@ -0,0 +1,157 @@
+use crate::db::*;
+use crate::error::*;
+use crate::tx::*;
+use std::{
+    ops::Bound,
+    sync::{Arc, RwLock},
+};
+
+/// Keys that can be scanned by prefix.
+///
+/// By default a key is only a prefix of itself. Strings and byte vectors
+/// match every key that starts with the same characters or bytes.
+pub trait Prefix: Sized + Clone {
+    /// The range of keys that start with `self`.
+    fn prefix_range(&self) -> (Bound<Self>, Bound<Self>) {
+        (Bound::Included(self.clone()), Bound::Included(self.clone()))
+    }
+}
+
+impl Prefix for String {
+    fn prefix_range(&self) -> (Bound<Self>, Bound<Self>) {
+        // Strings order by their UTF-8 bytes, which is the same as ordering by
+        // chars, so bumping the last char that can be bumped gives the end.
+        let mut end = self.clone();
+        while let Some(last) = end.pop() {
+            if let Some(next) = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32) {
+                end.push(next);
+                return (Bound::Included(self.clone()), Bound::Excluded(end));
+            }
+        }
+        (Bound::Included(self.clone()), Bound::Unbounded)
+    }
+}
+
+impl Prefix for Vec<u8> {
+    fn prefix_range(&self) -> (Bound<Self>, Bound<Self>) {
+        let mut end = self.clone();
+        while let Some(last) = end.pop() {
+            if last < u8::MAX {
+                end.push(last + 1);
+                return (Bound::Included(self.clone()), Bound::Excluded(end));
+            }
+        }
+        (Bound::Included(self.clone()), Bound::Unbounded)
+    }
+}
+
+macro_rules! exact_prefix {
+    ($($t:ty),+) => {
+        $(impl Prefix for $t {})+
+    };
+}
+
+exact_prefix!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl<A: Clone, B: Clone> Prefix for (A, B) {}
+impl<A: Clone, B: Clone, C: Clone> Prefix for (A, B, C) {}
+impl<A: Clone, B: Clone, C: Clone, D: Clone> Prefix for (A, B, C, D) {}
+
+/// The newest visible version of every key in a range, in key order.
+///
+/// Locks are only held while looking for the next key, so the range is never
+/// copied and other connections can keep writing while the scan is open.
+/// Which versions are visible is decided by the transaction's isolation
+/// level, exactly as for [`Command::Get`]. The whole range is recorded in the
+/// transaction's read ranges when the scan starts, and every key returned is
+/// added to its read set.
+pub struct Scan<K = KeyType, V = ValueType> {
+    db: Database<K, V>,
+    tx: Arc<RwLock<Transaction<K>>>,
+    start: Bound<K>,
+    end: Bound<K>,
+    reverse: bool,
+    done: bool,
+}
+
+impl<K: Ord + Clone, V: Clone> Scan<K, V> {
+    fn is_empty(&self) -> bool {
+        match (&self.start, &self.end) {
+            (Bound::Unbounded, _) | (_, Bound::Unbounded) => false,
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (Bound::Included(start) | Bound::Excluded(start), Bound::Excluded(end))
+            | (Bound::Excluded(start), Bound::Included(end)) => start >= end,
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Iterator for Scan<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        // `BTreeMap::range` panics on an empty range.
+        if self.done || self.is_empty() {
+            return None;
+        }
+        let kvlist = self.db.kvs_info.read().unwrap();
+        let txs_info = self.db.txs_info.read().unwrap();
+        let mut tx = self.tx.write().unwrap();
+        let visible = |(key, values): (&K, &Vec<Value<V>>)| {
+            Database::read_version(&txs_info, &tx, key, values)
+                .map(|v| (key.clone(), v.data.clone()))
+        };
+        let mut range = kvlist.range((self.start.clone(), self.end.clone()));
+        let row = if self.reverse {
+            range.rev().find_map(visible)
+        } else {
+            range.find_map(visible)
+        };
+
+        match &row {
+            Some((key, _)) => {
+                if self.reverse {
+                    self.end = Bound::Excluded(key.clone());
+                } else {
+                    self.start = Bound::Excluded(key.clone());
+                }
+                tx.read_set.insert(key.clone());
+            }
+            None => self.done = true,
+        }
+        row
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Connection<K, V> {
+    /// Scans the keys between `start` and `end`, in descending order if
+    /// `reverse`.
+    pub fn scan(
+        &self,
+        start: Bound<K>,
+        end: Bound<K>,
+        reverse: bool,
+    ) -> Result<Scan<K, V>, Error<K>> {
+        let tx = self.active_transaction()?;
+        tx.write()
+            .unwrap()
+            .read_ranges
+            .push((start.clone(), end.clone()));
+        Ok(Scan {
+            db: self.db.clone(),
+            tx,
+            start,
+            end,
+            reverse,
+            done: false,
+        })
+    }
+
+    /// Scans the keys that start with `prefix`.
+    pub fn scan_prefix(&self, prefix: &K, reverse: bool) -> Result<Scan<K, V>, Error<K>>
+    where
+        K: Prefix,
+    {
+        let (start, end) = prefix.prefix_range();
+        self.scan(start, end, reverse)
+    }
+}
@@ -0,0 +1,322 @@
+use crate::db::*;
+use crate::error::*;
+use crate::history::*;
+use crate::lock::*;
+use crate::scan::*;
+use crate::tx::*;
+use std::{fmt, sync::Arc};
+
+/// A small seeded generator (SplitMix64), so runs can be reproduced from
+/// their seed.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// A command run by one of the connections of a [`Simulation`].
+#[derive(PartialEq, Clone, Debug)]
+pub struct Step<K = KeyType, V = ValueType> {
+    pub connection: usize,
+    pub command: Command<K, V>,
+}
+
+/// What running a list of steps did.
+pub struct Run<K = KeyType, V = ValueType> {
+    pub db: Database<K, V>,
+    pub steps: Vec<Step<K, V>>,
+    /// The result of each step.
+    pub results: Vec<Result<Response<K, V>, Error<K>>>,
+    /// Every step, recorded by the connections.
+    pub history: History<K, V>,
+}
+
+/// A run an oracle rejected, shrunk to as few steps and as few switches
+/// between connections as still make it fail.
+pub struct Failure<K = KeyType, V = ValueType> {
+    /// The seed of the sampled schedule that failed first, if it was sampled.
+    pub seed: Option<u64>,
+    pub message: String,
+    pub steps: Vec<Step<K, V>>,
+    pub results: Vec<Result<Response<K, V>, Error<K>>>,
+}
+
+impl<K: fmt::Display + fmt::Debug + DisplayKey, V: fmt::Display + fmt::Debug> fmt::Display
+    for Failure<K, V>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(seed) = self.seed {
+            write!(f, " (seed {})", seed)?;
+        }
+        for (step, result) in self.steps.iter().zip(&self.results) {
+            write!(f, "\nc{}: {:?} => ", step.connection, step.command)?;
+            match result {
+                Ok(response) => write!(f, "{}", response)?,
+                Err(e) => write!(f, "ERROR: {}", e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Shows the same as `Display`, so a failure unwrapped in a test prints the
+/// interleaving.
+impl<K: fmt::Display + fmt::Debug + DisplayKey, V: fmt::Display + fmt::Debug> fmt::Debug
+    for Failure<K, V>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Every schedule of the scripts, in lexicographic order. A schedule names
+/// the connection that runs its next command at each step.
+pub struct Schedules {
+    next: Option<Vec<usize>>,
+}
+
+impl Iterator for Schedules {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let schedule = self.next.take()?;
+        let mut next = schedule.clone();
+        // The next permutation of a multiset.
+        if let Some(i) = (1..next.len()).rev().find(|&i| next[i - 1] < next[i]) {
+            let j = (i..next.len())
+                .rev()
+                .find(|&j| next[j] > next[i - 1])
+                .unwrap();
+            next.swap(i - 1, j);
+            next[i..].reverse();
+            self.next = Some(next);
+        }
+        Some(schedule)
+    }
+}
+
+/// Runs transaction scripts, one per connection, in many interleavings
+/// against a fresh database each time, and checks every run with an oracle.
+///
+/// Everything runs on the calling thread, so the lock wait policy is set to
+/// [`WaitPolicy::NoWait`]: a command that would wait for a lock fails with
+/// [`Error::LockConflict`] instead.
+pub struct Simulation<K = KeyType, V = ValueType> {
+    scripts: Vec<Vec<Command<K, V>>>,
+    setup: Arc<dyn Fn() -> Database<K, V>>,
+}
+
+impl<K: Prefix + Ord + Clone + 'static, V: Clone + PartialEq + 'static> Simulation<K, V> {
+    pub fn new(scripts: Vec<Vec<Command<K, V>>>) -> Self {
+        Simulation {
+            scripts,
+            setup: Arc::new(Database::new),
+        }
+    }
+
+    /// Makes each run start from the database `setup` returns instead of an
+    /// empty one, e.g. one at another isolation level or with data in it.
+    pub fn setup(mut self, setup: impl Fn() -> Database<K, V> + 'static) -> Self {
+        self.setup = Arc::new(setup);
+        self
+    }
+
+    /// The steps of `schedule`, which names a connection once for each of
+    /// its commands.
+    pub fn interleave(&self, schedule: &[usize]) -> Vec<Step<K, V>> {
+        let mut next = vec![0; self.scripts.len()];
+        schedule
+            .iter()
+            .map(|&connection| {
+                let command = self.scripts[connection][next[connection]].clone();
+                next[connection] += 1;
+                Step {
+                    connection,
+                    command,
+                }
+            })
+            .collect()
+    }
+
+    /// Every schedule, see [`Schedules`].
+    pub fn schedules(&self) -> Schedules {
+        let first = (0..self.scripts.len())
+            .flat_map(|i| std::iter::repeat_n(i, self.scripts[i].len()))
+            .collect();
+        Schedules { next: Some(first) }
+    }
+
+    /// A schedule picked from `seed`, every interleaving being equally
+    /// likely.
+    pub fn random_schedule(&self, seed: u64) -> Vec<usize> {
+        let mut rng = Rng::new(seed);
+        let mut left: Vec<usize> = self.scripts.iter().map(Vec::len).collect();
+        let mut schedule = Vec::new();
+        let mut total: usize = left.iter().sum();
+        while total > 0 {
+            let mut pick = rng.below(total);
+            let connection = left
+                .iter()
+                .position(|&n| {
+                    if pick < n {
+                        return true;
+                    }
+                    pick -= n;
+                    false
+                })
+                .unwrap();
+            left[connection] -= 1;
+            total -= 1;
+            schedule.push(connection);
+        }
+        schedule
+    }
+
+    /// Runs `steps` against a fresh database.
+    pub fn run(&self, steps: &[Step<K, V>]) -> Run<K, V> {
+        let mut db = (self.setup)();
+        db.lock_wait_policy = WaitPolicy::NoWait;
+        let history = History::new();
+        let mut connections: Vec<_> = (0..self.scripts.len())
+            .map(|_| {
+                let mut c = db.new_connection();
+                c.history = Some(history.clone());
+                c
+            })
+            .collect();
+        let results = steps
+            .iter()
+            .map(|step| connections[step.connection].exec_command(step.command.clone()))
+            .collect();
+        Run {
+            db,
+            steps: steps.to_vec(),
+            results,
+            history,
+        }
+    }
+
+    /// Checks every schedule, returning how many there were.
+    pub fn check_all<F>(&self, oracle: F) -> Result<usize, Failure<K, V>>
+    where
+        F: Fn(&Run<K, V>) -> Result<(), String>,
+    {
+        let mut count = 0;
+        for schedule in self.schedules() {
+            self.check(&self.interleave(&schedule), None, &oracle)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Checks `runs` schedules sampled from `seed`.
+    pub fn check_random<F>(&self, seed: u64, runs: usize, oracle: F) -> Result<(), Failure<K, V>>
+    where
+        F: Fn(&Run<K, V>) -> Result<(), String>,
+    {
+        let mut rng = Rng::new(seed);
+        for _ in 0..runs {
+            let seed = rng.next_u64();
+            let steps = self.interleave(&self.random_schedule(seed));
+            self.check(&steps, Some(seed), &oracle)?;
+        }
+        Ok(())
+    }
+
+    fn check<F>(
+        &self,
+        steps: &[Step<K, V>],
+        seed: Option<u64>,
+        oracle: &F,
+    ) -> Result<(), Failure<K, V>>
+    where
+        F: Fn(&Run<K, V>) -> Result<(), String>,
+    {
+        match oracle(&self.run(steps)) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(self.shrink(steps.to_vec(), seed, oracle)),
+        }
+    }
+
+    /// Drops steps and swaps neighbouring steps of different connections for
+    /// as long as the run still fails and gets shorter or switches
+    /// connections less often.
+    fn shrink<F>(&self, mut steps: Vec<Step<K, V>>, seed: Option<u64>, oracle: &F) -> Failure<K, V>
+    where
+        F: Fn(&Run<K, V>) -> Result<(), String>,
+    {
+        let fails = |steps: &[Step<K, V>]| oracle(&self.run(steps)).is_err();
+        let switches = |steps: &[Step<K, V>]| {
+            steps
+                .windows(2)
+                .filter(|w| w[0].connection != w[1].connection)
+                .count()
+        };
+        loop {
+            let mut shrunk = false;
+            let mut i = 0;
+            while i < steps.len() {
+                let mut candidate = steps.clone();
+                candidate.remove(i);
+                if fails(&candidate) {
+                    steps = candidate;
+                    shrunk = true;
+                } else {
+                    i += 1;
+                }
+            }
+            for i in 1..steps.len() {
+                if steps[i - 1].connection == steps[i].connection {
+                    continue;
+                }
+                let mut candidate = steps.clone();
+                candidate.swap(i - 1, i);
+                if switches(&candidate) < switches(&steps) && fails(&candidate) {
+                    steps = candidate;
+                    shrunk = true;
+                }
+            }
+            if !shrunk {
+                break;
+            }
+        }
+        let run = self.run(&steps);
+        Failure {
+            seed,
+            message: oracle(&run).unwrap_err(),
+            steps,
+            results: run.results,
+        }
+    }
+}
+
+/// An oracle that fails runs whose history shows any anomaly, see
+/// [`check`].
+pub fn serializable<K: Ord + Clone, V: Clone + PartialEq>(run: &Run<K, V>) -> Result<(), String> {
+    let anomalies = run.history.check();
+    if anomalies.is_empty() {
+        return Ok(());
+    }
+    let found: Vec<String> = anomalies
+        .iter()
+        .map(|(anomaly, txs)| format!("{} on transactions {:?}", anomaly, txs))
+        .collect();
+    Err(found.join(", "))
+}
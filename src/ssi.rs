@@ -0,0 +1,133 @@
+use crate::db::*;
+use crate::error::*;
+use crate::tx::*;
+use std::{
+    ops::RangeBounds,
+    sync::{Arc, RwLock},
+};
+
+/// Serializable snapshot isolation, after Cahill et al. and Ports and Grittner.
+///
+/// A transaction `r` has an rw-antidependency on a concurrent transaction `w`
+/// when `r` read a key, or scanned a range holding a key, that `w` wrote, so
+/// `r` did not see `w`'s write. Every history that is not serializable has a
+/// cycle with two such edges in a row, `in -rw-> pivot -rw-> out`, where `out`
+/// is the first of the three to commit. Serializable transactions only abort
+/// when that dangerous structure shows up, not on every overlap.
+///
+/// Edges are found when the later of the two transactions commits, since
+/// that is when both its read and write sets are complete as far as the other
+/// one is concerned. They are kept in `in_conflicts` / `out_conflicts` along
+/// with the key involved, a non-empty map is the paper's conflict flag.
+impl<K: Ord + Clone, V: Clone> Database<K, V> {
+    /// The key `reader` read or scanned that `writer` wrote, if any.
+    fn rw_key(reader: &Transaction<K>, writer: &Transaction<K>) -> Option<K> {
+        writer
+            .write_set
+            .iter()
+            .find(|key| {
+                reader.read_set.contains(*key)
+                    || reader.read_ranges.iter().any(|range| range.contains(*key))
+            })
+            .cloned()
+    }
+
+    /// Whether a structure `in_tx -rw-> pivot -rw-> out_tx` can be part of a
+    /// cycle, given that `out_tx` committed at `out_ts`.
+    ///
+    /// `out_tx` has to commit before `in_tx`. If `in_tx` is read-only, or
+    /// committed without writing anything, it can only be part of a cycle when
+    /// `out_tx` is in its snapshot.
+    fn dangerous(in_tx: &Transaction<K>, out_tx: TxIdType, out_ts: u64) -> bool {
+        if in_tx.read_only {
+            return in_tx.sees(out_tx, out_ts);
+        }
+        if in_tx.state != TransactionState::Committed {
+            return true;
+        }
+        out_ts <= in_tx.commit_ts && (!in_tx.write_set.is_empty() || in_tx.sees(out_tx, out_ts))
+    }
+
+    /// Records the rw-antidependencies between `tx` and every concurrent
+    /// transaction, then checks whether committing `tx` would complete a
+    /// dangerous structure.
+    pub(crate) fn serializable_check(
+        txs_info: &TxInfo<K>,
+        tx: &Arc<RwLock<Transaction<K>>>,
+    ) -> Option<Error<K>> {
+        // Only this thread takes two transaction locks at once, since it holds
+        // the `txs_info` write lock.
+        let concurrent: Vec<TxIdType> = {
+            let tx = tx.read().unwrap();
+            txs_info.concurrent(&tx)
+        };
+        for other_id in concurrent {
+            let Some(other) = txs_info.txs.get(&other_id) else {
+                continue;
+            };
+            let mut tx = tx.write().unwrap();
+            let mut other = other.write().unwrap();
+            if other.state == TransactionState::Aborted {
+                continue;
+            }
+            if let Some(key) = Self::rw_key(&tx, &other) {
+                tx.out_conflicts.insert(other.id, key.clone());
+                other.in_conflicts.insert(tx.id, key);
+            }
+            if other.isolation_level == IsolationLevel::Serializable {
+                if let Some(key) = Self::rw_key(&other, &tx) {
+                    other.out_conflicts.insert(tx.id, key.clone());
+                    tx.in_conflicts.insert(other.id, key);
+                }
+            }
+        }
+
+        let tx = tx.read().unwrap();
+        let conflict = |other_id: TxIdType| Error::ReadWriteConflict {
+            key: tx.out_conflicts[&other_id].clone(),
+            other_tx: other_id,
+        };
+
+        // `tx` is the pivot, with an out edge to a committed transaction.
+        for &out_id in tx.out_conflicts.keys() {
+            let Some(out_ts) = txs_info.committed_at(out_id) else {
+                continue;
+            };
+            for in_id in tx.in_conflicts.keys() {
+                if let Some(in_tx) = txs_info.txs.get(in_id) {
+                    let in_tx = in_tx.read().unwrap();
+                    if in_tx.state != TransactionState::Aborted
+                        && Self::dangerous(&in_tx, out_id, out_ts)
+                    {
+                        return Some(conflict(out_id));
+                    }
+                }
+            }
+        }
+
+        // `tx` is the last to commit, reading past a committed pivot whose
+        // out edge committed before it.
+        for &pivot_id in tx.out_conflicts.keys() {
+            let Some(pivot_ts) = txs_info.committed_at(pivot_id) else {
+                continue;
+            };
+            let Some(pivot) = txs_info.txs.get(&pivot_id) else {
+                continue;
+            };
+            let pivot = pivot.read().unwrap();
+            for &out_id in pivot.out_conflicts.keys() {
+                if out_id == tx.id {
+                    continue;
+                }
+                let Some(out_ts) = txs_info.committed_at(out_id) else {
+                    continue;
+                };
+                if out_ts <= pivot_ts && (!tx.write_set.is_empty() || tx.sees(out_id, out_ts)) {
+                    return Some(conflict(pivot_id));
+                }
+            }
+        }
+
+        None
+    }
+}
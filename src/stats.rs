@@ -0,0 +1,205 @@
+use crate::db::*;
+use std::collections::BTreeMap;
+#[cfg(feature = "prometheus")]
+use std::fmt::Write;
+
+/// Why a transaction was aborted.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum AbortReason {
+    /// The client asked for it with [`Command::Abort`](crate::tx::Command::Abort).
+    User,
+    /// A concurrent transaction wrote a key this one wrote.
+    WriteWriteConflict,
+    /// A Serializable transaction would have closed a dangerous structure.
+    ReadWriteConflict,
+    /// It was picked to break a deadlock.
+    Deadlock,
+    /// Its commit could not be written to the write-ahead log.
+    Io,
+    /// It was still running when the log ended.
+    Recovery,
+}
+
+/// How many transactions began, committed and aborted.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct TxCounts {
+    pub begun: u64,
+    pub committed: u64,
+    pub aborted: u64,
+}
+
+/// The transaction counters a database keeps as it runs.
+#[derive(Default)]
+pub(crate) struct Counters {
+    by_isolation: BTreeMap<IsolationLevel, TxCounts>,
+    aborts: BTreeMap<AbortReason, u64>,
+}
+
+impl Counters {
+    pub(crate) fn begun(&mut self, level: &IsolationLevel) {
+        self.by_isolation.entry(level.clone()).or_default().begun += 1;
+    }
+
+    pub(crate) fn committed(&mut self, level: &IsolationLevel) {
+        self.by_isolation
+            .entry(level.clone())
+            .or_default()
+            .committed += 1;
+    }
+
+    pub(crate) fn aborted(&mut self, level: &IsolationLevel, reason: AbortReason) {
+        self.by_isolation.entry(level.clone()).or_default().aborted += 1;
+        *self.aborts.entry(reason).or_default() += 1;
+    }
+}
+
+/// What [`Database::stats`] reports.
+///
+/// Transactions are counted since the database was created or opened, not
+/// while the write-ahead log is replayed, so the ones aborted by recovery
+/// were never counted as begun.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Stats {
+    pub transactions: TxCounts,
+    pub by_isolation: BTreeMap<IsolationLevel, TxCounts>,
+    pub aborts: BTreeMap<AbortReason, u64>,
+    pub active_transactions: usize,
+    /// Keys with a value as of the latest commit.
+    pub live_keys: usize,
+    /// Every version stored, of every key.
+    pub versions: usize,
+    /// Versions the next [`Database::vacuum`] would remove.
+    pub dead_versions: usize,
+    /// Versions per stored key, 0 with no keys.
+    pub avg_chain_length: f64,
+}
+
+impl<K: Ord + Clone, V: Clone> Database<K, V> {
+    /// The transaction counters and what the store holds right now.
+    pub fn stats(&self) -> Stats {
+        let kvlist = self.kvs_info.read().unwrap();
+        let txs_info = self.txs_info.read().unwrap();
+        let history_horizon = txs_info.history_horizon();
+        let active = Self::snapshot_readers(&txs_info);
+        let mut stats = Stats {
+            active_transactions: txs_info.get_active_tx().len(),
+            ..Default::default()
+        };
+        for values in kvlist.values() {
+            stats.versions += values.len();
+            stats.dead_versions += values
+                .iter()
+                .filter(|v| Self::dead(&txs_info, &active, history_horizon, v))
+                .count();
+            let live = values.iter().any(|v| {
+                txs_info.committed_at(v.tx_start_id).is_some()
+                    && txs_info.committed_at(v.tx_end_id).is_none()
+            });
+            stats.live_keys += live as usize;
+        }
+        if !kvlist.is_empty() {
+            stats.avg_chain_length = stats.versions as f64 / kvlist.len() as f64;
+        }
+        drop(active);
+
+        let counters = self.counters.lock().unwrap();
+        for (level, counts) in &counters.by_isolation {
+            stats.transactions.begun += counts.begun;
+            stats.transactions.committed += counts.committed;
+            stats.transactions.aborted += counts.aborted;
+            stats.by_isolation.insert(level.clone(), *counts);
+        }
+        stats.aborts = counters.aborts.clone();
+        stats
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl Stats {
+    /// The stats in the Prometheus text exposition format, every metric
+    /// prefixed with `rrmvcc_`.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        self.per_isolation(&mut out, "begun", "Transactions begun.", |c| c.begun);
+        self.per_isolation(&mut out, "committed", "Transactions committed.", |c| {
+            c.committed
+        });
+        self.per_isolation(&mut out, "aborted", "Transactions aborted.", |c| c.aborted);
+
+        let name = "rrmvcc_transaction_aborts_total";
+        header(
+            &mut out,
+            name,
+            "Transactions aborted, by reason.",
+            "counter",
+        );
+        for (reason, count) in &self.aborts {
+            let reason = snake_case(&format!("{:?}", reason));
+            let _ = writeln!(out, "{}{{reason=\"{}\"}} {}", name, reason, count);
+        }
+
+        let gauges = [
+            (
+                "active_transactions",
+                "Transactions running.",
+                self.active_transactions as f64,
+            ),
+            (
+                "live_keys",
+                "Keys with a value as of the latest commit.",
+                self.live_keys as f64,
+            ),
+            ("versions", "Versions stored.", self.versions as f64),
+            (
+                "dead_versions",
+                "Versions vacuum would remove.",
+                self.dead_versions as f64,
+            ),
+            (
+                "avg_chain_length",
+                "Versions per stored key.",
+                self.avg_chain_length,
+            ),
+        ];
+        for (name, help, value) in gauges {
+            let name = format!("rrmvcc_{}", name);
+            header(&mut out, &name, help, "gauge");
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+
+    fn per_isolation(
+        &self,
+        out: &mut String,
+        name: &str,
+        help: &str,
+        count: impl Fn(&TxCounts) -> u64,
+    ) {
+        let name = format!("rrmvcc_transactions_{}_total", name);
+        header(out, &name, help, "counter");
+        for (level, counts) in &self.by_isolation {
+            let level = format!("{:?}", level).to_lowercase();
+            let _ = writeln!(out, "{}{{isolation=\"{}\"}} {}", name, level, count(counts));
+        }
+    }
+}
+
+#[cfg(feature = "prometheus")]
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// `WriteWriteConflict` as `write_write_conflict`.
+#[cfg(feature = "prometheus")]
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    out
+}
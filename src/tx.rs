@@ -1,18 +1,146 @@
 use crate::db::*;
 #[allow(unused)]
 use crate::debug_info;
+use crate::error::*;
+use crate::history::*;
+use crate::lock::*;
+use crate::observe;
+use crate::savepoint::*;
+use crate::scan::*;
 #[allow(unused)]
 use crate::utils::*;
-use std::{cell::RefCell, collections::BTreeSet, rc::Rc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    ops::Bound,
+    sync::{Arc, RwLock},
+};
+
+/// How [`Command::BeginWith`] starts a transaction.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct BeginOptions {
+    /// Falls back to [`Database::default_isolation_level`].
+    pub isolation: Option<IsolationLevel>,
+    /// Writes are rejected. Serializable read-only transactions are also less
+    /// likely to make others abort.
+    pub read_only: bool,
+    /// Reads the database as it was right after the commit with this
+    /// timestamp. The transaction is read-only and runs at
+    /// [`IsolationLevel::Snapshot`], and the timestamp has to be within the
+    /// history kept, see [`Database::set_history_retention`].
+    pub as_of: Option<u64>,
+}
 
 #[derive(PartialEq, Debug, Clone)]
-pub enum Command {
+pub enum Command<K = KeyType, V = ValueType> {
     Begin,
+    BeginWith(BeginOptions),
     Abort,
     Commit,
-    Get(KeyType),
-    Set(KeyType, ValueType),
-    Delete(KeyType),
+    Get(K),
+    /// Reads the key after taking an exclusive lock on it.
+    GetForUpdate(K),
+    /// Reads the key after taking a shared lock on it.
+    GetForShare(K),
+    Set(K, V),
+    Delete(K),
+    /// The visible keys between `start` and `end`, at most `limit` of them.
+    Scan {
+        start: Bound<K>,
+        end: Bound<K>,
+        limit: Option<usize>,
+        reverse: bool,
+    },
+    /// The visible keys starting with `prefix`, see [`Prefix`].
+    ScanPrefix {
+        prefix: K,
+        limit: Option<usize>,
+        reverse: bool,
+    },
+    Savepoint(String),
+    /// Undoes everything written since the savepoint, which stays set.
+    RollbackTo(String),
+    /// Forgets the savepoint and the ones set after it, keeping their writes.
+    Release(String),
+}
+
+/// The result of a [`Command`] that succeeded.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Response<K = KeyType, V = ValueType> {
+    Begun {
+        tx_id: TxIdType,
+    },
+    /// `value` is `None` if no version of the key is visible.
+    Value {
+        tx_id: TxIdType,
+        key: K,
+        value: Option<V>,
+    },
+    Written {
+        tx_id: TxIdType,
+        key: K,
+        value: V,
+    },
+    Deleted {
+        tx_id: TxIdType,
+        key: K,
+    },
+    /// The rows of a scan, in the order they were scanned.
+    Rows {
+        tx_id: TxIdType,
+        rows: Vec<(K, V)>,
+    },
+    SavepointSet {
+        tx_id: TxIdType,
+        name: String,
+    },
+    RolledBack {
+        tx_id: TxIdType,
+        name: String,
+    },
+    Released {
+        tx_id: TxIdType,
+        name: String,
+    },
+    Committed {
+        tx_id: TxIdType,
+    },
+    Aborted {
+        tx_id: TxIdType,
+    },
+}
+
+impl<K: fmt::Display, V: fmt::Display> fmt::Display for Response<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Response::Begun { .. } => write!(f, "[BEGIN] finish"),
+            Response::Value {
+                key,
+                value: Some(value),
+                ..
+            } => write!(f, "[GET] key:{}, val:{}", key, value),
+            Response::Value {
+                key, value: None, ..
+            } => write!(f, "[GET] key {} not found", key),
+            Response::Written { key, value, .. } => write!(f, "[SET] key:{}, val:{}", key, value),
+            Response::Deleted { key, .. } => write!(f, "[DELETE] key:{}", key),
+            Response::Rows { rows, .. } if rows.is_empty() => write!(f, "[SCAN] no rows"),
+            Response::Rows { rows, .. } => {
+                for (i, (key, value)) in rows.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "[SCAN] key:{}, val:{}", key, value)?;
+                }
+                Ok(())
+            }
+            Response::SavepointSet { name, .. } => write!(f, "[SAVEPOINT] {}", name),
+            Response::RolledBack { name, .. } => write!(f, "[ROLLBACK TO] {}", name),
+            Response::Released { name, .. } => write!(f, "[RELEASE] {}", name),
+            Response::Committed { .. } => write!(f, "[COMMIT] finish"),
+            Response::Aborted { .. } => write!(f, "[ABORT] finish"),
+        }
+    }
 }
 
 #[derive(PartialEq, Clone, Debug)]
@@ -23,136 +151,231 @@ pub enum TransactionState {
 }
 
 #[derive(Debug)]
-pub struct Transaction {
+pub struct Transaction<K = KeyType> {
     pub id: u64,
     pub state: TransactionState,
     pub isolation_level: IsolationLevel,
+    pub read_only: bool,
+    pub snapshot_mode: SnapshotMode,
+    /// The timestamp of the last commit before this transaction began.
+    pub snapshot_ts: u64,
+    /// The transactions running when this one began, only filled in
+    /// [`SnapshotMode::ActiveSet`].
     pub inprogress: BTreeSet<TxIdType>,
-    pub write_set: BTreeSet<KeyType>,
-    pub read_set: BTreeSet<KeyType>,
+    pub write_set: BTreeSet<K>,
+    pub read_set: BTreeSet<K>,
+    /// Every range scanned, so that Serializable transactions also notice
+    /// keys inserted into a range they read.
+    pub read_ranges: Vec<(Bound<K>, Bound<K>)>,
+    /// Concurrent Serializable transactions that read a key this one wrote,
+    /// with that key.
+    pub in_conflicts: BTreeMap<TxIdType, K>,
+    /// Concurrent transactions that wrote a key this one read, with that key.
+    pub out_conflicts: BTreeMap<TxIdType, K>,
+    /// The versions this transaction ended, by key and writer.
+    pub ended: Vec<(K, TxIdType)>,
+    /// When this transaction committed, 0 until it does.
+    pub commit_ts: u64,
+    /// Every write since the oldest savepoint.
+    pub undo: Vec<Undo<K>>,
+    pub savepoints: Vec<Savepoint<K>>,
 }
 
-pub struct Connection<'a> {
-    pub tx: Option<Rc<RefCell<Transaction>>>,
-    pub db: &'a Database,
+impl<K> Transaction<K> {
+    /// Whether the snapshot of this transaction includes the commit of
+    /// `tx_id` at `commit_ts`.
+    pub fn sees(&self, tx_id: TxIdType, commit_ts: u64) -> bool {
+        match self.snapshot_mode {
+            SnapshotMode::CommitTimestamp => commit_ts <= self.snapshot_ts,
+            SnapshotMode::ActiveSet => tx_id < self.id && !self.inprogress.contains(&tx_id),
+        }
+    }
+}
+
+pub struct Connection<K = KeyType, V = ValueType> {
+    pub tx: Option<Arc<RwLock<Transaction<K>>>>,
+    pub db: Database<K, V>,
+    /// Where the commands run on this connection are recorded, if anywhere.
+    pub history: Option<History<K, V>>,
+}
+
+impl<K: Ord + Clone, V: Clone> Connection<K, V> {
+    /// The transaction commands run in, if it is still active.
+    pub(crate) fn active_transaction(&self) -> Result<Arc<RwLock<Transaction<K>>>, Error<K>> {
+        let tx = self.tx.as_ref().ok_or(Error::NoActiveTransaction)?;
+        let tx_id: TxIdType = tx.read().unwrap().id;
+        self.db.assert_transaction(tx_id)?;
+        Ok(Arc::clone(tx))
+    }
 }
 
-impl<'a> Connection<'a> {
-    pub fn exec_command(&mut self, command: Command) -> Result<String, String> {
+impl<K: Prefix + Ord + Clone, V: Clone> Connection<K, V> {
+    /// Runs `command` in the connection's transaction.
+    ///
+    /// Scans are collected into [`Response::Rows`], use [`Connection::scan`] to
+    /// stream a large range instead.
+    pub fn exec_command(&mut self, command: Command<K, V>) -> Result<Response<K, V>, Error<K>> {
+        if self.history.is_none() && self.db.observer.is_none() {
+            return self.exec(command, &mut None);
+        }
+        let was_active = self.active_transaction().is_ok();
+        let mut observed = None;
+        let result = self.exec(command.clone(), &mut observed);
+        self.db.emit(|| observe::Event::Command {
+            tx_id: self.tx.as_ref().map(|tx| tx.read().unwrap().id),
+            command: command.clone(),
+            outcome: result.clone(),
+        });
+        if let Some(history) = &self.history {
+            history.record(self.tx.as_ref(), was_active, command, &result, observed);
+        }
+        result
+    }
+
+    /// Runs `command`, setting `observed` to the transaction that wrote the
+    /// version a read returned or a write replaced.
+    fn exec(
+        &mut self,
+        command: Command<K, V>,
+        observed: &mut Option<TxIdType>,
+    ) -> Result<Response<K, V>, Error<K>> {
         match command {
-            Command::Begin => {
-                self.tx = Some(self.db.new_transaction());
-                if let Some(tx) = &self.tx {
-                    let tx_id: TxIdType = tx.as_ref().borrow().id.clone();
-                    self.db.assert_transaction(tx_id);
-                    return Ok("[BEGIN] finish".to_string());
-                }
-                return Err("[BEGIN] no active transaction".to_string());
+            Command::Begin => self.exec(Command::BeginWith(BeginOptions::default()), observed),
+            Command::BeginWith(options) => {
+                let tx = self.db.begin_transaction(options)?;
+                let tx_id: TxIdType = tx.read().unwrap().id;
+                self.tx = Some(tx);
+                Ok(Response::Begun { tx_id })
             }
             Command::Abort => {
-                if let Some(tx) = &self.tx {
-                    let tx_id: TxIdType = tx.as_ref().borrow().id.clone();
-                    self.db.assert_transaction(tx_id);
-                    let tx_id: TxIdType = tx.as_ref().borrow().id.clone();
-                    self.db
-                        .complete_transaction(tx_id, TransactionState::Aborted)?;
-                    return Ok("[ABORT] finish".to_string());
-                }
-                return Err("[ABORT] no active transaction".to_string());
+                let tx = self.active_transaction()?;
+                let tx_id: TxIdType = tx.read().unwrap().id;
+                self.db
+                    .complete_transaction(tx_id, TransactionState::Aborted)?;
+                Ok(Response::Aborted { tx_id })
             }
             Command::Commit => {
-                if let Some(tx) = &self.tx {
-                    let tx_id: TxIdType = tx.as_ref().borrow().id.clone();
-                    self.db.assert_transaction(tx_id);
-                    self.db
-                        .complete_transaction(tx_id, TransactionState::Committed)?;
-                    return Ok("[COMMIT] finish".to_string());
-                }
-                return Err("[COMMIT] no active transaction".to_string());
+                let tx = self.active_transaction()?;
+                let tx_id: TxIdType = tx.read().unwrap().id;
+                self.db
+                    .complete_transaction(tx_id, TransactionState::Committed)?;
+                Ok(Response::Committed { tx_id })
             }
             Command::Get(key) => {
-                if let Some(tx) = &self.tx {
-                    {
-                        let mut tx_mut = tx.as_ref().borrow_mut();
-                        tx_mut.read_set.insert(key.clone());
-                    }
-                    let tx_id: TxIdType = tx.as_ref().borrow().id.clone();
-                    self.db.assert_transaction(tx_id);
-                    let kvlist = self.db.kvs_info.as_ref().borrow();
-                    if let Some(values) = kvlist.get(&key) {
-                        if let Some(val) = values.iter().rfind(|v| self.db.is_visible(&tx, v)) {
-                            return Ok(format!("[GET] key:{}, val:{}", key, val.data));
-                        }
-                    }
-                    return Err(format!("[GET] key {} not found", key.clone()));
-                }
-                return Err("[GET] no active transaction".to_string());
+                let tx = self.active_transaction()?;
+                self.get(&tx, key, observed)
             }
-            Command::Set(key, val) => {
-                if let Some(tx) = &self.tx {
-                    let tx_id: TxIdType = tx.as_ref().borrow().id.clone();
-                    self.db.assert_transaction(tx_id);
-                    {
-                        let mut tx_mut = tx.as_ref().borrow_mut();
-                        tx_mut.write_set.insert(key.clone());
-                    }
-                    let mut kvlist = self.db.kvs_info.as_ref().borrow_mut();
-                    if let Some(values) = kvlist.get_mut(&key) {
-                        values
-                            .iter_mut()
-                            .rev()
-                            .filter(|v| self.db.is_visible(&tx, *v))
-                            .for_each(|v| v.tx_end_id = tx.as_ref().borrow().id);
-
-                        values.push(Value {
-                            data: val.clone(),
-                            tx_start_id: tx.as_ref().borrow().id,
-                            tx_end_id: 0,
-                        });
-                        return Ok(format!("[SET] key:{}, val:{}", key, val));
-                    } else {
-                        kvlist.insert(
-                            key.clone(),
-                            vec![Value {
-                                data: val.clone(),
-                                tx_start_id: tx.as_ref().borrow().id,
-                                tx_end_id: 0,
-                            }],
-                        );
-                        return Ok(format!("[SET] key:{}, val:{}", key, val));
-                    }
-                }
-                return Err("[SET] no active transaction".to_string());
+            Command::GetForUpdate(key) => {
+                let tx = self.active_transaction()?;
+                self.db.lock_key(&tx, &key, LockMode::Exclusive)?;
+                self.get(&tx, key, observed)
+            }
+            Command::GetForShare(key) => {
+                let tx = self.active_transaction()?;
+                self.db.lock_key(&tx, &key, LockMode::Shared)?;
+                self.get(&tx, key, observed)
+            }
+            Command::Set(key, value) => {
+                let tx = self.active_transaction()?;
+                self.db.first_updater_check(&tx, &key)?;
+                *observed = self.db.set_value(&tx, key.clone(), value.clone())?;
+                let tx_id: TxIdType = tx.read().unwrap().id;
+                Ok(Response::Written { tx_id, key, value })
             }
             Command::Delete(key) => {
-                if let Some(tx) = &self.tx {
-                    let tx_id: TxIdType = tx.as_ref().borrow().id.clone();
-                    self.db.assert_transaction(tx_id);
+                let tx = self.active_transaction()?;
+                self.db.first_updater_check(&tx, &key)?;
+                *observed = self.db.delete_value(&tx, key.clone())?;
+                let tx_id: TxIdType = tx.read().unwrap().id;
+                Ok(Response::Deleted { tx_id, key })
+            }
+            Command::Scan {
+                start,
+                end,
+                limit,
+                reverse,
+            } => {
+                let scan = self.scan(start, end, reverse)?;
+                self.collect_rows(scan, limit)
+            }
+            Command::ScanPrefix {
+                prefix,
+                limit,
+                reverse,
+            } => {
+                let scan = self.scan_prefix(&prefix, reverse)?;
+                self.collect_rows(scan, limit)
+            }
+            Command::Savepoint(name) => {
+                let tx = self.active_transaction()?;
+                self.db.savepoint(&tx, name.clone())?;
+                let tx_id: TxIdType = tx.read().unwrap().id;
+                Ok(Response::SavepointSet { tx_id, name })
+            }
+            Command::RollbackTo(name) => {
+                let tx = self.active_transaction()?;
+                self.db.rollback_to(&tx, name.clone())?;
+                let tx_id: TxIdType = tx.read().unwrap().id;
+                Ok(Response::RolledBack { tx_id, name })
+            }
+            Command::Release(name) => {
+                let tx = self.active_transaction()?;
+                self.db.release(&tx, name.clone())?;
+                let tx_id: TxIdType = tx.read().unwrap().id;
+                Ok(Response::Released { tx_id, name })
+            }
+        }
+    }
 
-                    let mut kvlist = self.db.kvs_info.as_ref().borrow_mut();
-                    if let Some(values) = kvlist.get_mut(&key) {
-                        let mut fonnd = false;
-                        values
-                            .iter_mut()
-                            .rev()
-                            .filter(|v| self.db.is_visible(&tx, *v))
-                            .for_each(|v| {
-                                v.tx_end_id = tx.as_ref().borrow().id;
-                                fonnd = true;
-                            });
+    fn collect_rows(
+        &self,
+        scan: Scan<K, V>,
+        limit: Option<usize>,
+    ) -> Result<Response<K, V>, Error<K>> {
+        let tx_id: TxIdType = self.active_transaction()?.read().unwrap().id;
+        let rows = scan.take(limit.unwrap_or(usize::MAX)).collect();
+        Ok(Response::Rows { tx_id, rows })
+    }
 
-                        if !fonnd {
-                            return Err(format!("[DELETE] key {} not found", key));
-                        }
-                        {
-                            let mut tx_mut = tx.as_ref().borrow_mut();
-                            tx_mut.write_set.insert(key.clone());
-                        }
-                    }
-                    return Ok(format!("[DELETE] key:{}", key));
-                }
-                return Err("[DELETE] no active transaction".to_string());
-            }
+    fn get(
+        &self,
+        tx: &Arc<RwLock<Transaction<K>>>,
+        key: K,
+        observed: &mut Option<TxIdType>,
+    ) -> Result<Response<K, V>, Error<K>> {
+        {
+            let mut tx_mut = tx.write().unwrap();
+            tx_mut.read_set.insert(key.clone());
+        }
+        let (tx_id, value, checked) = {
+            let kvlist = self.db.kvs_info.read().unwrap();
+            let txs_info = self.db.txs_info.read().unwrap();
+            let tx = tx.read().unwrap();
+            let values = kvlist.get(&key).map_or(&[][..], Vec::as_slice);
+            let version = Database::read_version(&txs_info, &tx, &key, values);
+            *observed = version.map(|v| v.tx_start_id);
+            let own = tx.write_set.contains(&key);
+            let checked: Vec<_> = match self.db.observer {
+                Some(_) => values
+                    .iter()
+                    .map(|v| {
+                        let visible = Database::readable(&txs_info, &tx, own, v);
+                        (v.tx_start_id, v.tx_end_id, visible)
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            (tx.id, version.map(|v| v.data.clone()), checked)
+        };
+        for (tx_start_id, tx_end_id, visible) in checked {
+            self.db.emit(|| observe::Event::Visibility {
+                tx_id,
+                key: Some(key.clone()),
+                tx_start_id,
+                tx_end_id,
+                visible,
+            });
         }
+        Ok(Response::Value { tx_id, key, value })
     }
 }
@@ -1,6 +1,6 @@
 #![allow(unused)]
 
-use std::{cell::RefCell, rc::Rc};
+use std::sync::{Arc, RwLock};
 #[derive(Debug, PartialEq)]
 pub enum BorrowState {
     Unused,
@@ -27,10 +27,10 @@ macro_rules! debug_info {
     };
 }
 
-pub fn debug_get_borrow_state<T>(rc_refcell: &Rc<RefCell<T>>) -> BorrowState {
-    if rc_refcell.try_borrow_mut().is_ok() {
+pub fn debug_get_borrow_state<T>(arc_rwlock: &Arc<RwLock<T>>) -> BorrowState {
+    if arc_rwlock.try_write().is_ok() {
         BorrowState::Unused
-    } else if rc_refcell.try_borrow().is_ok() {
+    } else if arc_rwlock.try_read().is_ok() {
         BorrowState::Reading
     } else {
         BorrowState::Writing
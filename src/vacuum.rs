@@ -0,0 +1,161 @@
+use crate::db::*;
+use crate::tx::*;
+use std::{
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex, RwLockReadGuard,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// What a vacuum pass reclaimed.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct VacuumStats {
+    pub versions_reclaimed: usize,
+    pub transactions_reclaimed: usize,
+}
+
+impl VacuumStats {
+    fn add(&mut self, other: VacuumStats) {
+        self.versions_reclaimed += other.versions_reclaimed;
+        self.transactions_reclaimed += other.transactions_reclaimed;
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Database<K, V> {
+    /// Removes every version that no active or future snapshot can see, then
+    /// forgets the finished transactions nothing refers to any more. History
+    /// within [`Database::set_history_retention`] is kept for time-travel reads.
+    ///
+    /// A version is dead once it was created by an aborted transaction, or
+    /// ended by a committed transaction before the retained history and hidden
+    /// from every active one. Time-travel reads from before the retained
+    /// history are refused from then on, so no new transaction sees such a
+    /// version. `ReadUncommitted` readers are ignored, they only ever read the
+    /// newest version of a key.
+    pub fn vacuum(&self) -> VacuumStats {
+        let mut kvlist = self.kvs_info.write().unwrap();
+        let mut txs_info = self.txs_info.write().unwrap();
+        let history_horizon = txs_info.history_horizon();
+        txs_info.vacuumed_to = history_horizon;
+        let horizon = txs_info.oldest_snapshot(history_horizon);
+        let mut stats = VacuumStats::default();
+
+        let active = Self::snapshot_readers(&txs_info);
+        kvlist.retain(|_, values| {
+            let before = values.len();
+            values.retain(|v| !Self::dead(&txs_info, &active, history_horizon, v));
+            // An end stamp left by an aborted transaction never took effect.
+            values
+                .iter_mut()
+                .filter(|v| {
+                    txs_info.get_transaction_state(v.tx_end_id) == Some(TransactionState::Aborted)
+                })
+                .for_each(|v| v.tx_end_id = 0);
+            stats.versions_reclaimed += before - values.len();
+            !values.is_empty()
+        });
+
+        drop(active);
+
+        // Aborted transactions no longer appear in any version, and the
+        // committed ones below the horizon are reported as committed once
+        // they are gone, see `TxInfo::get_transaction_state`.
+        let before = txs_info.txs.len();
+        txs_info.txs.retain(|tx_id, _| *tx_id >= horizon);
+        stats.transactions_reclaimed = before - txs_info.txs.len();
+        stats
+    }
+
+    /// The active transactions whose snapshots keep versions alive.
+    pub(crate) fn snapshot_readers(
+        txs_info: &TxInfo<K>,
+    ) -> Vec<RwLockReadGuard<'_, Transaction<K>>> {
+        txs_info
+            .txs
+            .values()
+            .map(|tx| tx.read().unwrap())
+            .filter(|tx| {
+                tx.state == TransactionState::Active
+                    && tx.isolation_level != IsolationLevel::ReadUncommitted
+            })
+            .collect()
+    }
+
+    /// Whether vacuum would remove `v`, see [`Database::vacuum`].
+    pub(crate) fn dead(
+        txs_info: &TxInfo<K>,
+        active: &[RwLockReadGuard<'_, Transaction<K>>],
+        history_horizon: u64,
+        v: &Value<V>,
+    ) -> bool {
+        let created = txs_info.get_transaction_state(v.tx_start_id);
+        let ended = txs_info.committed_at(v.tx_end_id);
+        created == Some(TransactionState::Aborted)
+            || (ended.is_some_and(|ts| ts <= history_horizon)
+                && active.iter().all(|tx| !Self::visible(txs_info, tx, v)))
+    }
+
+    /// Runs [`Database::vacuum`] every `interval` on a background thread until
+    /// the returned handle is stopped or dropped.
+    pub fn spawn_vacuum(&self, interval: Duration) -> VacuumHandle
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        let db = self.clone();
+        let total = Arc::new(Mutex::new(VacuumStats::default()));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = {
+            let total = Arc::clone(&total);
+            thread::spawn(move || loop {
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {
+                        let stats = db.vacuum();
+                        total.lock().unwrap().add(stats);
+                    }
+                    _ => return,
+                }
+            })
+        };
+        VacuumHandle {
+            stop: Some(stop),
+            thread: Some(thread),
+            total,
+        }
+    }
+}
+
+/// A background vacuum task started by [`Database::spawn_vacuum`].
+pub struct VacuumHandle {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    total: Arc<Mutex<VacuumStats>>,
+}
+
+impl VacuumHandle {
+    /// Everything reclaimed by the task so far.
+    pub fn stats(&self) -> VacuumStats {
+        *self.total.lock().unwrap()
+    }
+
+    /// Stops the task and waits for it, returning everything it reclaimed.
+    pub fn stop(mut self) -> VacuumStats {
+        self.shutdown();
+        self.stats()
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+impl Drop for VacuumHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
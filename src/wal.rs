@@ -0,0 +1,389 @@
+use crate::db::*;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    marker::PhantomData,
+    path::Path,
+    sync::Mutex,
+};
+
+/// When the write-ahead log is flushed to stable storage.
+///
+/// Every record is handed to the OS as soon as it is appended, so a process
+/// crash never loses an acknowledged commit. The policy only decides how often
+/// `fsync` runs, which is what protects against power loss.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum SyncPolicy {
+    /// fsync after every commit or abort record.
+    Always,
+    /// fsync after every `n` commit or abort records.
+    Every(u32),
+    /// Never fsync, leave it to the OS.
+    Never,
+}
+
+/// How keys and values are written to the write-ahead log.
+pub trait Codec: Sized {
+    fn encode(&self, buf: &mut Vec<u8>);
+    /// Reads a value back from the front of `buf`, advancing past it.
+    fn decode(buf: &mut &[u8]) -> Option<Self>;
+}
+
+impl Codec for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_bytes().to_vec().encode(buf);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        String::from_utf8(Vec::decode(buf)?).ok()
+    }
+}
+
+impl Codec for Vec<u8> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        let len = take_u32(buf)? as usize;
+        Some(take(buf, len)?.to_vec())
+    }
+}
+
+macro_rules! int_codec {
+    ($($t:ty),+) => {
+        $(
+            impl Codec for $t {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(buf: &mut &[u8]) -> Option<Self> {
+                    let bytes = take(buf, std::mem::size_of::<$t>())?;
+                    Some(<$t>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )+
+    };
+}
+
+int_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+macro_rules! tuple_codec {
+    ($($t:ident),+) => {
+        impl<$($t: Codec),+> Codec for ($($t,)+) {
+            #[allow(non_snake_case)]
+            fn encode(&self, buf: &mut Vec<u8>) {
+                let ($($t,)+) = self;
+                $($t.encode(buf);)+
+            }
+
+            fn decode(buf: &mut &[u8]) -> Option<Self> {
+                Some(($($t::decode(buf)?,)+))
+            }
+        }
+    };
+}
+
+tuple_codec!(A, B);
+tuple_codec!(A, B, C);
+tuple_codec!(A, B, C, D);
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Record<K = KeyType, V = ValueType> {
+    Begin {
+        tx_id: TxIdType,
+        isolation_level: IsolationLevel,
+    },
+    Set {
+        tx_id: TxIdType,
+        key: K,
+        value: V,
+    },
+    Delete {
+        tx_id: TxIdType,
+        key: K,
+    },
+    Commit {
+        tx_id: TxIdType,
+    },
+    Abort {
+        tx_id: TxIdType,
+    },
+    Savepoint {
+        tx_id: TxIdType,
+        name: String,
+    },
+    RollbackTo {
+        tx_id: TxIdType,
+        name: String,
+    },
+    Release {
+        tx_id: TxIdType,
+        name: String,
+    },
+}
+
+const TAG_BEGIN: u8 = 1;
+const TAG_SET: u8 = 2;
+const TAG_DELETE: u8 = 3;
+const TAG_COMMIT: u8 = 4;
+const TAG_ABORT: u8 = 5;
+const TAG_SAVEPOINT: u8 = 6;
+const TAG_ROLLBACK_TO: u8 = 7;
+const TAG_RELEASE: u8 = 8;
+
+/// Every record is framed as `[len: u32][crc32: u32][payload]`, so a torn
+/// write at the tail of the file is detected and dropped on replay.
+const HEADER_LEN: usize = 8;
+
+impl<K: Codec, V: Codec> Record<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Record::Begin {
+                tx_id,
+                isolation_level,
+            } => {
+                buf.push(TAG_BEGIN);
+                buf.extend_from_slice(&tx_id.to_le_bytes());
+                buf.push(isolation_level_to_u8(isolation_level));
+            }
+            Record::Set { tx_id, key, value } => {
+                buf.push(TAG_SET);
+                buf.extend_from_slice(&tx_id.to_le_bytes());
+                key.encode(buf);
+                value.encode(buf);
+            }
+            Record::Delete { tx_id, key } => {
+                buf.push(TAG_DELETE);
+                buf.extend_from_slice(&tx_id.to_le_bytes());
+                key.encode(buf);
+            }
+            Record::Commit { tx_id } => {
+                buf.push(TAG_COMMIT);
+                buf.extend_from_slice(&tx_id.to_le_bytes());
+            }
+            Record::Abort { tx_id } => {
+                buf.push(TAG_ABORT);
+                buf.extend_from_slice(&tx_id.to_le_bytes());
+            }
+            Record::Savepoint { tx_id, name } => {
+                buf.push(TAG_SAVEPOINT);
+                buf.extend_from_slice(&tx_id.to_le_bytes());
+                name.encode(buf);
+            }
+            Record::RollbackTo { tx_id, name } => {
+                buf.push(TAG_ROLLBACK_TO);
+                buf.extend_from_slice(&tx_id.to_le_bytes());
+                name.encode(buf);
+            }
+            Record::Release { tx_id, name } => {
+                buf.push(TAG_RELEASE);
+                buf.extend_from_slice(&tx_id.to_le_bytes());
+                name.encode(buf);
+            }
+        }
+    }
+
+    fn decode(mut buf: &[u8]) -> Option<Record<K, V>> {
+        let buf = &mut buf;
+        let record = match take_u8(buf)? {
+            TAG_BEGIN => Record::Begin {
+                tx_id: take_u64(buf)?,
+                isolation_level: isolation_level_from_u8(take_u8(buf)?)?,
+            },
+            TAG_SET => Record::Set {
+                tx_id: take_u64(buf)?,
+                key: K::decode(buf)?,
+                value: V::decode(buf)?,
+            },
+            TAG_DELETE => Record::Delete {
+                tx_id: take_u64(buf)?,
+                key: K::decode(buf)?,
+            },
+            TAG_COMMIT => Record::Commit {
+                tx_id: take_u64(buf)?,
+            },
+            TAG_ABORT => Record::Abort {
+                tx_id: take_u64(buf)?,
+            },
+            TAG_SAVEPOINT => Record::Savepoint {
+                tx_id: take_u64(buf)?,
+                name: String::decode(buf)?,
+            },
+            TAG_ROLLBACK_TO => Record::RollbackTo {
+                tx_id: take_u64(buf)?,
+                name: String::decode(buf)?,
+            },
+            TAG_RELEASE => Record::Release {
+                tx_id: take_u64(buf)?,
+                name: String::decode(buf)?,
+            },
+            _ => return None,
+        };
+        if !buf.is_empty() {
+            return None;
+        }
+        Some(record)
+    }
+}
+
+impl<K, V> Record<K, V> {
+    fn is_completion(&self) -> bool {
+        matches!(self, Record::Commit { .. } | Record::Abort { .. })
+    }
+}
+
+fn isolation_level_to_u8(level: &IsolationLevel) -> u8 {
+    match level {
+        IsolationLevel::ReadUncommitted => 0,
+        IsolationLevel::ReadCommitted => 1,
+        IsolationLevel::RepeatableRead => 2,
+        IsolationLevel::Snapshot => 3,
+        IsolationLevel::Serializable => 4,
+    }
+}
+
+fn isolation_level_from_u8(level: u8) -> Option<IsolationLevel> {
+    match level {
+        0 => Some(IsolationLevel::ReadUncommitted),
+        1 => Some(IsolationLevel::ReadCommitted),
+        2 => Some(IsolationLevel::RepeatableRead),
+        3 => Some(IsolationLevel::Snapshot),
+        4 => Some(IsolationLevel::Serializable),
+        _ => None,
+    }
+}
+
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if buf.len() < n {
+        return None;
+    }
+    let (head, tail) = buf.split_at(n);
+    *buf = tail;
+    Some(head)
+}
+
+fn take_u8(buf: &mut &[u8]) -> Option<u8> {
+    take(buf, 1).map(|b| b[0])
+}
+
+fn take_u32(buf: &mut &[u8]) -> Option<u32> {
+    take(buf, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+fn take_u64(buf: &mut &[u8]) -> Option<u64> {
+    take(buf, 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+struct WalFile {
+    file: File,
+    unsynced: u32,
+}
+
+/// An append-only log of every change made to a durable [`Database`].
+pub struct Wal<K = KeyType, V = ValueType> {
+    inner: Mutex<WalFile>,
+    policy: SyncPolicy,
+    // Captured when the log is opened, so that appending needs no `Codec`
+    // bound and in-memory databases work with any key and value type.
+    encode: fn(&Record<K, V>, &mut Vec<u8>),
+    records: PhantomData<fn(Record<K, V>)>,
+}
+
+impl<K: Codec, V: Codec> Wal<K, V> {
+    /// Opens the log at `path`, returning it along with every intact record.
+    ///
+    /// Anything after the last intact record is a torn write from a crash and
+    /// is cut off, so that new records are not appended after garbage.
+    pub fn open(path: &Path, policy: SyncPolicy) -> io::Result<(Self, Vec<Record<K, V>>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while let Some((record, len)) = Wal::read_record(&data[offset..]) {
+            records.push(record);
+            offset += len;
+        }
+        if offset < data.len() {
+            file.set_len(offset as u64)?;
+            file.sync_all()?;
+        }
+
+        let wal = Wal {
+            inner: Mutex::new(WalFile { file, unsynced: 0 }),
+            policy,
+            encode: Record::encode,
+            records: PhantomData,
+        };
+        Ok((wal, records))
+    }
+
+    fn read_record(data: &[u8]) -> Option<(Record<K, V>, usize)> {
+        let mut buf = data;
+        let len = take_u32(&mut buf)? as usize;
+        let crc = take_u32(&mut buf)?;
+        let payload = take(&mut buf, len)?;
+        if crc32(payload) != crc {
+            return None;
+        }
+        Some((Record::decode(payload)?, HEADER_LEN + len))
+    }
+}
+
+impl<K, V> Wal<K, V> {
+    pub fn append(&self, record: &Record<K, V>) -> io::Result<()> {
+        let mut payload = Vec::new();
+        (self.encode)(record, &mut payload);
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&crc32(&payload).to_le_bytes());
+        frame.extend_from_slice(&payload);
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.file.write_all(&frame)?;
+        if record.is_completion() {
+            inner.unsynced += 1;
+            let sync = match self.policy {
+                SyncPolicy::Always => true,
+                SyncPolicy::Every(n) => inner.unsynced >= n,
+                SyncPolicy::Never => false,
+            };
+            if sync {
+                inner.file.sync_data()?;
+                inner.unsynced = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Forces everything appended so far to stable storage.
+    pub fn sync(&self) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.file.sync_data()?;
+        inner.unsynced = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{get, setup};
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use std::ops::Bound;
+
+    /// Hermitage-style: every level against every anomaly, `fail` where the
+    /// anomaly showed up and `pass` where the level prevented it.
+    const MATRIX: &str = "\
+anomaly             RU    RC    RR    SI    SER
+dirty write         fail  fail  fail  pass  pass
+dirty read          fail  pass  pass  pass  pass
+fuzzy read          fail  fail  pass  pass  pass
+lost update         fail  fail  fail  pass  pass
+read skew           fail  fail  pass  pass  pass
+write skew          fail  fail  fail  fail  pass
+phantom             fail  fail  pass  pass  pass
+read-only anomaly   fail  fail  fail  fail  pass
+";
+
+    const LEVELS: [(&str, IsolationLevel); 5] = [
+        ("RU", IsolationLevel::ReadUncommitted),
+        ("RC", IsolationLevel::ReadCommitted),
+        ("RR", IsolationLevel::RepeatableRead),
+        ("SI", IsolationLevel::Snapshot),
+        ("SER", IsolationLevel::Serializable),
+    ];
+
+    type Scenario = fn(&Database) -> bool;
+
+    const ANOMALIES: [(&str, Scenario); 8] = [
+        ("dirty write", dirty_write),
+        ("dirty read", dirty_read),
+        ("fuzzy read", fuzzy_read),
+        ("lost update", lost_update),
+        ("read skew", read_skew),
+        ("write skew", write_skew),
+        ("phantom", phantom),
+        ("read-only anomaly", read_only_anomaly),
+    ];
+
+    fn begin(db: &Database) -> Connection {
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c
+    }
+
+    fn set(c: &mut Connection, key: &str, val: &str) -> bool {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .is_ok()
+    }
+
+    fn commit(c: &mut Connection) -> bool {
+        c.exec_command(Command::Commit).is_ok()
+    }
+
+    fn count(c: &mut Connection) -> usize {
+        match c.exec_command(Command::Scan {
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+            limit: None,
+            reverse: false,
+        }) {
+            Ok(Response::Rows { rows, .. }) => rows.len(),
+            ret => panic!("unexpected {:?}", ret),
+        }
+    }
+
+    /// What a fresh Snapshot transaction reads for `keys`.
+    fn committed(db: &Database, keys: &[&str]) -> Vec<Option<String>> {
+        let mut c = db.new_connection();
+        c.exec_command(Command::BeginWith(BeginOptions {
+            isolation: Some(IsolationLevel::Snapshot),
+            ..Default::default()
+        }))
+        .unwrap();
+        keys.iter().map(|key| get(&mut c, key)).collect()
+    }
+
+    fn value(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    /// G0: both write `x` and `y` in opposite orders, and the result mixes
+    /// their writes.
+    fn dirty_write(db: &Database) -> bool {
+        let mut c1 = begin(db);
+        let mut c2 = begin(db);
+        set(&mut c1, "x", "11");
+        set(&mut c2, "x", "12");
+        set(&mut c2, "y", "22");
+        set(&mut c1, "y", "21");
+        commit(&mut c1);
+        commit(&mut c2);
+        let state = committed(db, &["x", "y"]);
+        state == [value("12"), value("21")] || state == [value("11"), value("22")]
+    }
+
+    /// G1a: a write that is later aborted is read.
+    fn dirty_read(db: &Database) -> bool {
+        let mut c1 = begin(db);
+        let mut c2 = begin(db);
+        set(&mut c1, "x", "11");
+        let read = get(&mut c2, "x");
+        c1.exec_command(Command::Abort).unwrap();
+        read == value("11")
+    }
+
+    /// P2: reading `x` twice returns different values.
+    fn fuzzy_read(db: &Database) -> bool {
+        let mut c1 = begin(db);
+        let mut c2 = begin(db);
+        let first = get(&mut c1, "x");
+        set(&mut c2, "x", "11");
+        commit(&mut c2);
+        get(&mut c1, "x") != first
+    }
+
+    /// P4: both add to `x` and both commit, so one addition is lost.
+    fn lost_update(db: &Database) -> bool {
+        let mut c1 = begin(db);
+        let mut c2 = begin(db);
+        get(&mut c1, "x");
+        get(&mut c2, "x");
+        set(&mut c1, "x", "11");
+        set(&mut c2, "x", "12");
+        commit(&mut c1) && commit(&mut c2)
+    }
+
+    /// G-single: `x` is read before and `y` after another transaction moved
+    /// 2 from `y` to `x`, so the sum is off.
+    fn read_skew(db: &Database) -> bool {
+        let mut c1 = begin(db);
+        let mut c2 = begin(db);
+        let x = get(&mut c1, "x");
+        set(&mut c2, "x", "12");
+        set(&mut c2, "y", "18");
+        commit(&mut c2);
+        let y = get(&mut c1, "y");
+        (x, y) == (value("10"), value("18"))
+    }
+
+    /// G2-item: both read `x` and `y`, each writes one of them and both
+    /// commit.
+    fn write_skew(db: &Database) -> bool {
+        let mut c1 = begin(db);
+        let mut c2 = begin(db);
+        for c in [&mut c1, &mut c2] {
+            get(c, "x");
+            get(c, "y");
+        }
+        set(&mut c1, "x", "0");
+        set(&mut c2, "y", "0");
+        commit(&mut c1) && commit(&mut c2)
+    }
+
+    /// P3: scanning twice returns a key inserted in between.
+    fn phantom(db: &Database) -> bool {
+        let mut c1 = begin(db);
+        let mut c2 = begin(db);
+        let first = count(&mut c1);
+        set(&mut c2, "z", "30");
+        commit(&mut c2);
+        count(&mut c1) != first
+    }
+
+    /// Fekete's read-only anomaly: c3 sees c1's deposit to `y` but not c2's
+    /// withdrawal from `x`, while c2 did not see the deposit either.
+    fn read_only_anomaly(db: &Database) -> bool {
+        let mut c1 = begin(db);
+        let mut c2 = begin(db);
+        get(&mut c2, "x");
+        get(&mut c2, "y");
+        get(&mut c1, "y");
+        set(&mut c1, "y", "40");
+        let deposited = commit(&mut c1);
+        let mut c3 = begin(db);
+        get(&mut c3, "x");
+        get(&mut c3, "y");
+        let read = commit(&mut c3);
+        set(&mut c2, "x", "-11");
+        deposited && read && commit(&mut c2)
+    }
+
+    fn matrix() -> String {
+        let mut matrix = format!("{:<20}", "anomaly");
+        for (name, _) in &LEVELS {
+            matrix += &format!("{:<6}", name);
+        }
+        matrix = matrix.trim_end().to_string() + "\n";
+        for (anomaly, scenario) in ANOMALIES {
+            let mut row = format!("{:<20}", anomaly);
+            for (_, level) in &LEVELS {
+                let occurred = scenario(&setup(level.clone(), &[("x", "10"), ("y", "20")]));
+                row += if occurred { "fail  " } else { "pass  " };
+            }
+            matrix += row.trim_end();
+            matrix += "\n";
+        }
+        matrix
+    }
+
+    #[test]
+    fn test_anomaly_matrix() {
+        assert_eq!(matrix(), MATRIX);
+    }
+
+    #[test]
+    fn test_serializable_prevents_every_anomaly() {
+        for (anomaly, scenario) in ANOMALIES {
+            let db = setup(IsolationLevel::Serializable, &[("x", "10"), ("y", "20")]);
+            assert!(!scenario(&db), "{} under Serializable", anomaly);
+        }
+    }
+}
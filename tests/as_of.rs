@@ -0,0 +1,134 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::get;
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+
+    fn commit(db: &Database, command: Command) {
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c.exec_command(command).unwrap();
+        c.exec_command(Command::Commit).unwrap();
+    }
+
+    fn set(db: &Database, key: &str, val: &str) {
+        commit(db, Command::Set(key.to_string(), val.to_string()));
+    }
+
+    fn as_of(db: &Database, as_of: u64) -> Result<Connection, Error> {
+        let mut c = db.new_connection();
+        c.exec_command(Command::BeginWith(BeginOptions {
+            as_of: Some(as_of),
+            ..Default::default()
+        }))?;
+        Ok(c)
+    }
+
+    fn setup(history_retention: u64) -> Database {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::ReadCommitted;
+        db.set_history_retention(history_retention);
+        set(&db, "x", "1");
+        set(&db, "x", "2");
+        commit(&db, Command::Delete("x".to_string()));
+        set(&db, "y", "1");
+        db
+    }
+
+    #[test]
+    fn test_reads_past_commits() {
+        let db = setup(4);
+        db.vacuum();
+
+        let mut c = as_of(&db, 1).unwrap();
+        assert_eq!(get(&mut c, "x"), Some("1".to_string()));
+        assert_eq!(get(&mut c, "y"), None);
+        let mut c = as_of(&db, 2).unwrap();
+        assert_eq!(get(&mut c, "x"), Some("2".to_string()));
+        let mut c = as_of(&db, 3).unwrap();
+        assert_eq!(get(&mut c, "x"), None);
+        let mut c = as_of(&db, 4).unwrap();
+        assert_eq!(get(&mut c, "y"), Some("1".to_string()));
+
+        // Nothing committed since is seen.
+        set(&db, "y", "2");
+        assert_eq!(get(&mut c, "y"), Some("1".to_string()));
+        c.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_as_of_is_read_only() {
+        let db = setup(4);
+        let mut c = as_of(&db, 1).unwrap();
+        assert_eq!(
+            c.exec_command(Command::Set("x".to_string(), "3".to_string())),
+            Err(Error::ReadOnly(5))
+        );
+        assert_eq!(
+            c.tx.as_ref().unwrap().read().unwrap().isolation_level,
+            IsolationLevel::Snapshot
+        );
+    }
+
+    #[test]
+    fn test_reads_outside_the_retention_window_are_refused() {
+        let db = setup(2);
+        let out_of_range = |as_of| Error::AsOfOutOfRange {
+            as_of,
+            horizon: 2,
+            latest: 4,
+        };
+        assert_eq!(as_of(&db, 1).err(), Some(out_of_range(1)));
+        assert_eq!(as_of(&db, 5).err(), Some(out_of_range(5)));
+
+        db.vacuum();
+        let mut c = as_of(&db, 2).unwrap();
+        assert_eq!(get(&mut c, "x"), Some("2".to_string()));
+    }
+
+    #[test]
+    fn test_active_reader_keeps_its_history() {
+        let db = setup(1);
+        let mut c = as_of(&db, 4).unwrap();
+
+        // The horizon moves past the reader, which still sees its snapshot.
+        set(&db, "y", "2");
+        set(&db, "x", "3");
+        db.vacuum();
+        assert_eq!(get(&mut c, "x"), None);
+        assert_eq!(get(&mut c, "y"), Some("1".to_string()));
+        c.exec_command(Command::Commit).unwrap();
+
+        assert!(as_of(&db, 4).is_err());
+        db.vacuum();
+        assert_eq!(db.kvs_info.read().unwrap()["y"].len(), 1);
+    }
+
+    #[test]
+    fn test_retention_is_shared_by_every_handle() {
+        let db = setup(0);
+        let other = db.clone();
+        other.vacuum();
+
+        // What the vacuum pruned stays out of reach.
+        db.set_history_retention(4);
+        assert_eq!(
+            as_of(&db, 1).err(),
+            Some(Error::AsOfOutOfRange {
+                as_of: 1,
+                horizon: 4,
+                latest: 4
+            })
+        );
+
+        // A vacuum through another handle keeps what this one retains.
+        set(&db, "y", "2");
+        set(&db, "y", "3");
+        other.vacuum();
+        let mut c = as_of(&db, 4).unwrap();
+        assert_eq!(get(&mut c, "y"), Some("1".to_string()));
+    }
+}
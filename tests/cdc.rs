@@ -0,0 +1,174 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::cdc::*;
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::fs;
+    use std::sync::mpsc::TryRecvError;
+    use std::thread;
+
+    fn run(db: &Database, commands: Vec<Command>, end: Command) {
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        for command in commands {
+            c.exec_command(command).unwrap();
+        }
+        c.exec_command(end).unwrap();
+    }
+
+    fn set(key: &str, val: &str) -> Command {
+        Command::Set(key.to_string(), val.to_string())
+    }
+
+    fn put(key: &str, old: Option<&str>, new: &str) -> Change {
+        Change::Put {
+            key: key.to_string(),
+            old: old.map(str::to_string),
+            new: new.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_committed_changes() {
+        let db = Database::new();
+        let changes = db.subscribe();
+        run(&db, vec![set("x", "1"), set("y", "1")], Command::Commit);
+        // Only the last write of a key counts, and nothing that is undone.
+        run(
+            &db,
+            vec![
+                set("x", "2"),
+                set("x", "3"),
+                Command::Delete("y".to_string()),
+                set("z", "1"),
+                Command::Delete("z".to_string()),
+            ],
+            Command::Commit,
+        );
+        run(&db, vec![set("x", "4")], Command::Abort);
+        run(&db, vec![Command::Get("x".to_string())], Command::Commit);
+
+        assert_eq!(
+            changes.try_recv(),
+            Ok(Ok(ChangeEvent {
+                tx_id: 1,
+                commit_ts: 1,
+                changes: vec![put("x", None, "1"), put("y", None, "1")]
+            }))
+        );
+        assert_eq!(
+            changes.try_recv(),
+            Ok(Ok(ChangeEvent {
+                tx_id: 2,
+                commit_ts: 2,
+                changes: vec![
+                    put("x", Some("1"), "3"),
+                    Change::Delete {
+                        key: "y".to_string(),
+                        old: "1".to_string()
+                    }
+                ]
+            }))
+        );
+        assert_eq!(changes.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_subscribe_from() {
+        let db = Database::new();
+        // Kept for every handle, whichever one commits.
+        db.clone().set_change_retention(2);
+        for i in 1..=3 {
+            run(&db, vec![set("x", &i.to_string())], Command::Commit);
+        }
+
+        let changes = db.subscribe_from(3).unwrap();
+        run(&db, vec![set("x", "4")], Command::Commit);
+        let positions: Vec<_> = changes.try_iter().map(|e| e.unwrap().commit_ts).collect();
+        assert_eq!(positions, [3, 4]);
+        assert!(matches!(
+            db.subscribe_from(2),
+            Err(Error::ChangesOutOfRange {
+                from: 2,
+                horizon: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_stream_to_another_thread() {
+        let db = Database::new();
+        let changes = db.subscribe();
+        let consumer = thread::spawn(move || {
+            changes
+                .iter()
+                .take(10)
+                .map(|e| e.unwrap().commit_ts)
+                .collect::<Vec<_>>()
+        });
+        let writers: Vec<_> = (0..2)
+            .map(|t| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for i in 0..5 {
+                        run(
+                            &db,
+                            vec![set(&format!("{}.{}", t, i), "1")],
+                            Command::Commit,
+                        );
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(consumer.join().unwrap(), (1..=10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_lagging_subscriber_is_told_where_it_fell_behind() {
+        let db = Database::new();
+        db.set_change_retention(2 * CHANGE_BUFFER);
+        let lagging = db.subscribe();
+        let reading = db.subscribe();
+        for i in 0..=CHANGE_BUFFER {
+            run(&db, vec![set("x", &i.to_string())], Command::Commit);
+            assert!(reading.try_recv().unwrap().is_ok());
+        }
+
+        // It missed the last commit, and hears of it with the next one once
+        // it made room.
+        assert_eq!(lagging.try_iter().count(), CHANGE_BUFFER);
+        assert_eq!(lagging.try_recv(), Err(TryRecvError::Empty));
+        run(&db, vec![set("x", "last")], Command::Commit);
+        let from = CHANGE_BUFFER as u64 + 1;
+        assert_eq!(lagging.try_recv(), Ok(Err(Error::ChangesLagged { from })));
+        assert_eq!(lagging.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(reading.try_iter().count(), 1);
+
+        let resumed = db.subscribe_from(from).unwrap();
+        assert_eq!(resumed.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn test_reopened_database_streams_new_commits() {
+        let path = std::env::temp_dir().join(format!("rrmvcc-cdc-{}.wal", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let db = Database::open(&path).unwrap();
+            run(&db, vec![set("x", "1")], Command::Commit);
+        }
+        let db = Database::open(&path).unwrap();
+        db.set_change_retention(1);
+        assert!(db.subscribe_from(1).is_err());
+        let changes = db.subscribe_from(2).unwrap();
+        run(&db, vec![set("x", "2")], Command::Commit);
+        assert_eq!(
+            changes.try_recv().unwrap().unwrap().changes,
+            [put("x", Some("1"), "2")]
+        );
+        let _ = fs::remove_file(&path);
+    }
+}
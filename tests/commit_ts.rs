@@ -0,0 +1,104 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{self, get};
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+
+    fn set(c: &mut Connection, key: &str, val: &str) {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+    }
+
+    fn begin(db: &Database) -> Connection {
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c
+    }
+
+    fn transaction(c: &Connection) -> std::sync::RwLockReadGuard<'_, Transaction> {
+        c.tx.as_ref().unwrap().read().unwrap()
+    }
+
+    fn setup(snapshot_mode: SnapshotMode) -> Database {
+        let mut db = common::setup(IsolationLevel::Snapshot, &[("x", "0")]);
+        db.snapshot_mode = snapshot_mode;
+        db
+    }
+
+    #[test]
+    fn test_commit_timestamps_follow_commit_order() {
+        let db = setup(SnapshotMode::CommitTimestamp);
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        assert_eq!(transaction(&c1).snapshot_ts, 1);
+        assert!(transaction(&c2).inprogress.is_empty());
+
+        c2.exec_command(Command::Commit).unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+        assert_eq!(transaction(&c2).commit_ts, 2);
+        assert_eq!(transaction(&c1).commit_ts, 3);
+        assert_eq!(db.txs_info.read().unwrap().commit_ts, 3);
+
+        let c3 = begin(&db);
+        assert_eq!(transaction(&c3).snapshot_ts, 3);
+    }
+
+    #[test]
+    fn test_modes_agree() {
+        for mode in [SnapshotMode::CommitTimestamp, SnapshotMode::ActiveSet] {
+            let db = setup(mode);
+
+            // An older transaction that commits late is not in the snapshot,
+            // and neither is a younger one.
+            let mut older = begin(&db);
+            let mut reader = begin(&db);
+            let mut younger = begin(&db);
+            set(&mut older, "x", "1");
+            set(&mut younger, "y", "1");
+            older.exec_command(Command::Commit).unwrap();
+            younger.exec_command(Command::Commit).unwrap();
+            assert_eq!(get(&mut reader, "x"), Some("0".to_string()), "{:?}", mode);
+            assert_eq!(get(&mut reader, "y"), None, "{:?}", mode);
+
+            // Writing a key a concurrent transaction committed still loses.
+            set(&mut reader, "x", "2");
+            assert_eq!(
+                reader.exec_command(Command::Commit),
+                Err(Error::WriteWriteConflict {
+                    key: "x".to_string(),
+                    other_tx: 2
+                }),
+                "{:?}",
+                mode
+            );
+
+            let mut c = begin(&db);
+            assert_eq!(get(&mut c, "x"), Some("1".to_string()), "{:?}", mode);
+            assert_eq!(get(&mut c, "y"), Some("1".to_string()), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_vacuum_keeps_commits_after_the_oldest_snapshot() {
+        let db = setup(SnapshotMode::CommitTimestamp);
+        let mut writer = begin(&db);
+        let mut reader = begin(&db);
+        set(&mut writer, "x", "1");
+        writer.exec_command(Command::Commit).unwrap();
+
+        // The writer began first but committed after the reader's snapshot,
+        // so it has to stay known as a late commit.
+        db.vacuum();
+        assert!(db.txs_info.read().unwrap().txs.contains_key(&2));
+        assert_eq!(get(&mut reader, "x"), Some("0".to_string()));
+        reader.exec_command(Command::Commit).unwrap();
+
+        db.vacuum();
+        assert!(db.txs_info.read().unwrap().txs.is_empty());
+        let mut c = begin(&db);
+        assert_eq!(get(&mut c, "x"), Some("1".to_string()));
+    }
+}
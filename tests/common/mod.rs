@@ -0,0 +1,27 @@
+// Every test binary compiles this module, and none uses all of it.
+#![allow(dead_code)]
+
+use rrmvcc::db::*;
+use rrmvcc::tx::*;
+
+/// The value `c` reads for `key`, panicking if the read fails.
+pub fn get(c: &mut Connection, key: &str) -> Option<String> {
+    match c.exec_command(Command::Get(key.to_string())) {
+        Ok(Response::Value { value, .. }) => value,
+        ret => panic!("unexpected {:?}", ret),
+    }
+}
+
+/// A database at `level` where `pairs` were committed.
+pub fn setup(level: IsolationLevel, pairs: &[(&str, &str)]) -> Database {
+    let mut db = Database::new();
+    db.default_isolation_level = level;
+    let mut c = db.new_connection();
+    c.exec_command(Command::Begin).unwrap();
+    for (key, val) in pairs {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+    }
+    c.exec_command(Command::Commit).unwrap();
+    db
+}
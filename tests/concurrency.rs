@@ -0,0 +1,178 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::get;
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::thread;
+
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 50;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    fn key(name: &str, worker: usize, round: usize) -> String {
+        format!("{}-{}-{}", name, worker, round)
+    }
+
+    fn read_committed(db: &Database, worker: usize, round: usize) {
+        let mut db = db.clone();
+        db.default_isolation_level = IsolationLevel::ReadCommitted;
+        let x = key("rc", worker, round);
+
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        c1.exec_command(Command::Set(x.clone(), "hey".to_string()))
+            .unwrap();
+        assert_eq!(get(&mut c2, &x), None);
+        c1.exec_command(Command::Commit).unwrap();
+        assert_eq!(get(&mut c2, &x), Some("hey".to_string()));
+        c2.exec_command(Command::Commit).unwrap();
+    }
+
+    fn repeatable_read(db: &Database, worker: usize, round: usize) {
+        let mut db = db.clone();
+        db.default_isolation_level = IsolationLevel::RepeatableRead;
+        let x = key("rr", worker, round);
+
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        c1.exec_command(Command::Set(x.clone(), "hey".to_string()))
+            .unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+        assert_eq!(get(&mut c2, &x), None);
+
+        let mut c3 = db.new_connection();
+        c3.exec_command(Command::Begin).unwrap();
+        assert_eq!(get(&mut c3, &x), Some("hey".to_string()));
+        c3.exec_command(Command::Delete(x.clone())).unwrap();
+        c3.exec_command(Command::Commit).unwrap();
+        c2.exec_command(Command::Commit).unwrap();
+    }
+
+    fn snapshot(db: &Database, worker: usize, round: usize) {
+        let mut db = db.clone();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+        let x = key("si", worker, round);
+
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        c1.exec_command(Command::Set(x.clone(), "hey".to_string()))
+            .unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+        c2.exec_command(Command::Set(x.clone(), "hey".to_string()))
+            .unwrap();
+        assert!(matches!(
+            c2.exec_command(Command::Commit),
+            Err(Error::WriteWriteConflict { key, .. }) if key == x
+        ));
+    }
+
+    fn serializable(db: &Database, worker: usize, round: usize) {
+        let mut db = db.clone();
+        db.default_isolation_level = IsolationLevel::Serializable;
+        let x = key("ser-x", worker, round);
+        let y = key("ser-y", worker, round);
+
+        // Write skew: each one writes the key the other one read.
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        assert_eq!(get(&mut c1, &y), None);
+        assert_eq!(get(&mut c2, &x), None);
+        c1.exec_command(Command::Set(x.clone(), "hey".to_string()))
+            .unwrap();
+        c2.exec_command(Command::Set(y.clone(), "hey".to_string()))
+            .unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+        assert!(matches!(
+            c2.exec_command(Command::Commit),
+            Err(Error::ReadWriteConflict { key, .. }) if key == x
+        ));
+    }
+
+    #[test]
+    fn test_database_is_send_sync() {
+        assert_send_sync::<Database>();
+        assert_send_sync::<Connection>();
+    }
+
+    #[test]
+    fn test_parallel_isolation_scenarios() {
+        let db = Database::new();
+        let handles: Vec<_> = (0..THREADS)
+            .map(|worker| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for round in 0..ROUNDS {
+                        read_committed(&db, worker, round);
+                        repeatable_read(&db, worker, round);
+                        snapshot(&db, worker, round);
+                        serializable(&db, worker, round);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let txs_info = db.txs_info.read().unwrap();
+        assert_eq!(txs_info.next_tx_id as usize, 1 + THREADS * ROUNDS * 9);
+        assert!(txs_info
+            .txs
+            .values()
+            .all(|tx| tx.read().unwrap().state != TransactionState::Active));
+    }
+
+    #[test]
+    fn test_parallel_counter_increments() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c.exec_command(Command::Set("counter".to_string(), "0".to_string()))
+            .unwrap();
+        c.exec_command(Command::Commit).unwrap();
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    let mut c = db.new_connection();
+                    let mut committed = 0;
+                    while committed < ROUNDS {
+                        c.exec_command(Command::Begin).unwrap();
+                        let n: usize = get(&mut c, "counter").unwrap().parse().unwrap();
+                        c.exec_command(Command::Set("counter".to_string(), (n + 1).to_string()))
+                            .unwrap();
+                        if c.exec_command(Command::Commit).is_ok() {
+                            committed += 1;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        assert_eq!(get(&mut c, "counter"), Some((THREADS * ROUNDS).to_string()));
+    }
+}
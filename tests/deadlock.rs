@@ -0,0 +1,150 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::lock::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::thread;
+
+    fn set(c: &mut Connection, key: &str, val: &str) {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+    }
+
+    fn lock(c: &mut Connection, key: &str) -> Result<Response, Error> {
+        c.exec_command(Command::GetForUpdate(key.to_string()))
+    }
+
+    fn begin(db: &Database) -> Connection {
+        let mut c = db.new_connection();
+        c.exec_command(Command::BeginWith(BeginOptions {
+            isolation: Some(IsolationLevel::ReadCommitted),
+            ..Default::default()
+        }))
+        .unwrap();
+        c
+    }
+
+    /// Locks `key` for `c` on another thread, which commits once it has the
+    /// lock. Returns as soon as the thread waits.
+    fn lock_in_background(
+        db: &Database,
+        mut c: Connection,
+        key: &str,
+    ) -> thread::JoinHandle<Result<Response, Error>> {
+        let tx_id = c.tx.as_ref().unwrap().read().unwrap().id;
+        let key = key.to_string();
+        let handle = thread::spawn(move || {
+            let ret = lock(&mut c, &key)?;
+            c.exec_command(Command::Commit)?;
+            Ok(ret)
+        });
+        while !db.locks.waiting().contains_key(&tx_id) {
+            thread::yield_now();
+        }
+        handle
+    }
+
+    fn deadlock(key: &str, tx_id: TxIdType) -> Error {
+        Error::Deadlock {
+            key: key.to_string(),
+            tx_id,
+        }
+    }
+
+    #[test]
+    fn test_two_way_deadlock_aborts_the_youngest() {
+        let db = Database::new();
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        lock(&mut c1, "x").unwrap();
+        lock(&mut c2, "y").unwrap();
+
+        let waiter = lock_in_background(&db, c1, "y");
+        let err = lock(&mut c2, "x").unwrap_err();
+        assert_eq!(err, deadlock("x", 2));
+        assert!(err.is_retryable());
+        assert_eq!(
+            c2.exec_command(Command::Commit),
+            Err(Error::InvalidState {
+                tx_id: 2,
+                state: TransactionState::Aborted
+            })
+        );
+
+        waiter.join().unwrap().unwrap();
+        assert!(db.locks.waiting().is_empty());
+    }
+
+    #[test]
+    fn test_two_way_deadlock_aborts_the_fewest_writes() {
+        let mut db = Database::new();
+        db.deadlock_victim = DeadlockVictim::FewestWrites;
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        lock(&mut c1, "x").unwrap();
+        lock(&mut c2, "y").unwrap();
+        set(&mut c2, "a", "1");
+        set(&mut c2, "b", "1");
+
+        // c2 started last but did more work, so the waiting c1 gives up.
+        let waiter = lock_in_background(&db, c1, "y");
+        lock(&mut c2, "x").unwrap();
+        assert_eq!(waiter.join().unwrap(), Err(deadlock("y", 1)));
+        c2.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_three_way_deadlock() {
+        let db = Database::new();
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        let mut c3 = begin(&db);
+        lock(&mut c1, "x").unwrap();
+        lock(&mut c2, "y").unwrap();
+        lock(&mut c3, "z").unwrap();
+
+        // c1 -> c2 -> c3 -> c1, closed by c3.
+        let w1 = lock_in_background(&db, c1, "y");
+        let w2 = lock_in_background(&db, c2, "z");
+        assert_eq!(lock(&mut c3, "x"), Err(deadlock("x", 3)));
+
+        w2.join().unwrap().unwrap();
+        w1.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_three_way_deadlock_victim_is_another_waiter() {
+        let mut db = Database::new();
+        db.deadlock_victim = DeadlockVictim::FewestWrites;
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        let mut c3 = begin(&db);
+        lock(&mut c1, "x").unwrap();
+        lock(&mut c2, "y").unwrap();
+        lock(&mut c3, "z").unwrap();
+        set(&mut c1, "a", "1");
+        set(&mut c3, "b", "1");
+
+        let w1 = lock_in_background(&db, c1, "y");
+        let w2 = lock_in_background(&db, c2, "z");
+        // c2 is aborted, so c1 gets y and commits, then c3 gets x.
+        lock(&mut c3, "x").unwrap();
+        assert_eq!(w2.join().unwrap(), Err(deadlock("z", 2)));
+        w1.join().unwrap().unwrap();
+        c3.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_waiting_without_a_cycle() {
+        let db = Database::new();
+        let mut c1 = begin(&db);
+        let c2 = begin(&db);
+        lock(&mut c1, "x").unwrap();
+
+        let waiter = lock_in_background(&db, c2, "x");
+        lock(&mut c1, "y").unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+        waiter.join().unwrap().unwrap();
+    }
+}
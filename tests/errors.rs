@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+
+    #[test]
+    fn test_no_active_transaction() {
+        let db = Database::new();
+        let mut c = db.new_connection();
+        for command in [
+            Command::Commit,
+            Command::Abort,
+            Command::Get("x".to_string()),
+            Command::Set("x".to_string(), "hey".to_string()),
+            Command::Delete("x".to_string()),
+        ] {
+            assert_eq!(c.exec_command(command), Err(Error::NoActiveTransaction));
+        }
+    }
+
+    #[test]
+    fn test_finished_transaction() {
+        let db = Database::new();
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c.exec_command(Command::Commit).unwrap();
+
+        let committed = Error::InvalidState {
+            tx_id: 1,
+            state: TransactionState::Committed,
+        };
+        assert_eq!(c.exec_command(Command::Commit), Err(committed.clone()));
+        assert_eq!(
+            c.exec_command(Command::Set("x".to_string(), "hey".to_string())),
+            Err(committed.clone())
+        );
+        assert_eq!(
+            db.complete_transaction(1, TransactionState::Aborted),
+            Err(committed)
+        );
+        assert_eq!(db.assert_transaction(0), Err(Error::TransactionNotFound(0)));
+        assert_eq!(
+            db.complete_transaction(42, TransactionState::Committed),
+            Err(Error::TransactionNotFound(42))
+        );
+    }
+
+    #[test]
+    fn test_conflicts_are_retryable() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        c1.exec_command(Command::Set("x".to_string(), "hey".to_string()))
+            .unwrap();
+        c2.exec_command(Command::Set("x".to_string(), "yall".to_string()))
+            .unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+
+        let err = c2.exec_command(Command::Commit).unwrap_err();
+        assert!(err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "write-write conflict on key x with transaction 1"
+        );
+
+        let err = c2.exec_command(Command::Commit).unwrap_err();
+        assert!(!err.is_retryable());
+        assert_eq!(err.to_string(), "transaction 2 is Aborted");
+    }
+
+    #[test]
+    fn test_binary_and_composite_keys() {
+        let mut db: Database<Vec<u8>, u64> = Database::new();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c.exec_command(Command::Set(b"k".to_vec(), 1)).unwrap();
+        c.exec_command(Command::Commit).unwrap();
+
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c.exec_command(Command::Delete(b"k".to_vec())).unwrap();
+        c.exec_command(Command::Commit).unwrap();
+        // Deleted but still stored, so it is not found rather than absent.
+        let fails = || -> Result<(), Box<dyn std::error::Error>> {
+            let mut c = db.new_connection();
+            c.exec_command(Command::Begin)?;
+            c.exec_command(Command::Delete(b"k".to_vec()))?;
+            Ok(())
+        };
+        assert_eq!(fails().unwrap_err().to_string(), "key k not found");
+
+        let err: Error<Vec<u8>> = Error::KeyNotFound {
+            key: vec![b'a', 0xff],
+        };
+        assert_eq!(err.to_string(), "key a\\xff not found");
+        let err: Error<(String, u32)> = Error::LockTimeout {
+            key: ("users".to_string(), 7),
+        };
+        assert_eq!(
+            err.to_string(),
+            "timed out waiting for the lock on key (users, 7)"
+        );
+    }
+}
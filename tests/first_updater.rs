@@ -0,0 +1,131 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{self, get};
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::thread;
+
+    fn set(c: &mut Connection, key: &str, val: &str) -> Result<Response, Error> {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+    }
+
+    fn begin(db: &Database) -> Connection {
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c
+    }
+
+    fn conflict(other_tx: TxIdType) -> Error {
+        Error::WriteWriteConflict {
+            key: "x".to_string(),
+            other_tx,
+        }
+    }
+
+    fn aborted(tx_id: TxIdType) -> Error {
+        Error::InvalidState {
+            tx_id,
+            state: TransactionState::Aborted,
+        }
+    }
+
+    fn setup(write_conflicts: WriteConflicts) -> Database {
+        let mut db = common::setup(IsolationLevel::Snapshot, &[("x", "0")]);
+        db.write_conflicts = write_conflicts;
+        db
+    }
+
+    #[test]
+    fn test_fail_fast_on_uncommitted_writer() {
+        let db = setup(WriteConflicts::FailFast);
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        set(&mut c1, "x", "1").unwrap();
+        assert_eq!(set(&mut c2, "x", "2"), Err(conflict(2)));
+        assert_eq!(c2.exec_command(Command::Commit), Err(aborted(3)));
+        c1.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_fail_fast_on_committed_writer() {
+        let db = setup(WriteConflicts::FailFast);
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        set(&mut c1, "x", "1").unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+        assert_eq!(
+            c2.exec_command(Command::Delete("x".to_string())),
+            Err(conflict(2))
+        );
+
+        // A transaction that began after the commit sees it and may write.
+        let mut c3 = begin(&db);
+        set(&mut c3, "x", "3").unwrap();
+        c3.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_fail_fast_ignores_aborted_writers_and_other_keys() {
+        let db = setup(WriteConflicts::FailFast);
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        set(&mut c1, "x", "1").unwrap();
+        set(&mut c2, "y", "1").unwrap();
+        c1.exec_command(Command::Abort).unwrap();
+        set(&mut c2, "x", "2").unwrap();
+        c2.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_read_committed_is_not_checked() {
+        let db = setup(WriteConflicts::FailFast);
+        let mut c1 = begin(&db);
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::BeginWith(BeginOptions {
+            isolation: Some(IsolationLevel::ReadCommitted),
+            ..Default::default()
+        }))
+        .unwrap();
+        set(&mut c1, "x", "1").unwrap();
+        set(&mut c2, "x", "2").unwrap();
+    }
+
+    #[test]
+    fn test_wait_for_writer_that_commits() {
+        let db = setup(WriteConflicts::Wait);
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        set(&mut c1, "x", "1").unwrap();
+
+        let waiter = thread::spawn(move || set(&mut c2, "x", "2"));
+        while db.locks.waiting().is_empty() {
+            thread::yield_now();
+        }
+        c1.exec_command(Command::Commit).unwrap();
+        assert_eq!(waiter.join().unwrap(), Err(conflict(2)));
+    }
+
+    #[test]
+    fn test_wait_for_writer_that_aborts() {
+        let db = setup(WriteConflicts::Wait);
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        set(&mut c1, "x", "1").unwrap();
+
+        let waiter = thread::spawn(move || {
+            set(&mut c2, "x", "2")?;
+            c2.exec_command(Command::Commit)
+        });
+        while db.locks.waiting().is_empty() {
+            thread::yield_now();
+        }
+        c1.exec_command(Command::Abort).unwrap();
+        assert_eq!(waiter.join().unwrap(), Ok(Response::Committed { tx_id: 3 }));
+
+        let mut c3 = begin(&db);
+        assert_eq!(get(&mut c3, "x"), Some("2".to_string()));
+    }
+}
@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::fs;
+
+    type Key = (u64, u32);
+    type Payload = Vec<u8>;
+
+    fn get(c: &mut Connection<Key, Payload>, key: Key) -> Option<Payload> {
+        match c.exec_command(Command::Get(key)) {
+            Ok(Response::Value { value, .. }) => value,
+            ret => panic!("unexpected {:?}", ret),
+        }
+    }
+
+    #[test]
+    fn test_composite_keys_and_binary_values() {
+        let mut db: Database<Key, Payload> = Database::new();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        c1.exec_command(Command::Set((1, 2), vec![0, 159, 146, 150]))
+            .unwrap();
+        assert_eq!(get(&mut c1, (1, 2)), Some(vec![0, 159, 146, 150]));
+        assert_eq!(get(&mut c2, (1, 2)), None);
+
+        c2.exec_command(Command::Set((1, 2), vec![255])).unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+        assert_eq!(
+            c2.exec_command(Command::Commit),
+            Err(Error::WriteWriteConflict {
+                key: (1, 2),
+                other_tx: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_recover_binary_values() {
+        let path = std::env::temp_dir().join(format!("rrmvcc-generic-{}.wal", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let db: Database<Key, Payload> = Database::open(&path).unwrap();
+            let mut c = db.new_connection();
+            c.exec_command(Command::Begin).unwrap();
+            c.exec_command(Command::Set((7, 0), vec![])).unwrap();
+            c.exec_command(Command::Set((7, 1), (0..=255).collect()))
+                .unwrap();
+            c.exec_command(Command::Commit).unwrap();
+        }
+
+        let db: Database<Key, Payload> = Database::open(&path).unwrap();
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        assert_eq!(get(&mut c, (7, 0)), Some(vec![]));
+        assert_eq!(get(&mut c, (7, 1)), Some((0..=255).collect()));
+        drop(c);
+        drop(db);
+        fs::remove_file(&path).unwrap();
+    }
+}
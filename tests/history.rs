@@ -0,0 +1,166 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{get, setup};
+    use rrmvcc::db::*;
+    use rrmvcc::history::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::collections::BTreeMap;
+
+    fn set(c: &mut Connection, key: &str, val: &str) {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+    }
+
+    fn commit(c: &mut Connection) -> Result<Response, Error> {
+        c.exec_command(Command::Commit)
+    }
+
+    fn begin(history: &History, db: &Database, isolation: IsolationLevel) -> Connection {
+        let mut c = db.new_connection();
+        c.history = Some(history.clone());
+        c.exec_command(Command::BeginWith(BeginOptions {
+            isolation: Some(isolation),
+            ..Default::default()
+        }))
+        .unwrap();
+        c
+    }
+
+    fn anomaly(anomaly: Anomaly, txs: &[TxIdType]) -> BTreeMap<Anomaly, Vec<TxIdType>> {
+        BTreeMap::from([(anomaly, txs.to_vec())])
+    }
+
+    fn lost_update(isolation: IsolationLevel) -> History {
+        let db = setup(IsolationLevel::ReadUncommitted, &[("x", "0"), ("y", "0")]);
+        let history = History::new();
+        let mut c2 = begin(&history, &db, isolation.clone());
+        let mut c3 = begin(&history, &db, isolation);
+        get(&mut c2, "x");
+        get(&mut c3, "x");
+        set(&mut c2, "x", "2");
+        commit(&mut c2).unwrap();
+        set(&mut c3, "x", "3");
+        let _ = commit(&mut c3);
+        history
+    }
+
+    #[test]
+    fn test_recorded_events() {
+        let history = lost_update(IsolationLevel::Snapshot);
+        let event = |tx_id, op, outcome| Event { tx_id, op, outcome };
+        let begin = Operation::Begin {
+            isolation: IsolationLevel::Snapshot,
+        };
+        let read = Operation::Read {
+            key: "x".to_string(),
+            value: Some("0".to_string()),
+            writer: Some(1),
+        };
+        let write = |value: &str| Operation::Write {
+            key: "x".to_string(),
+            value: Some(value.to_string()),
+            replaced: Some(1),
+        };
+        let conflict = Error::WriteWriteConflict {
+            key: "x".to_string(),
+            other_tx: 2,
+        };
+        assert_eq!(
+            history.events(),
+            [
+                event(2, begin.clone(), Ok(())),
+                event(3, begin, Ok(())),
+                event(2, read.clone(), Ok(())),
+                event(3, read, Ok(())),
+                event(2, write("2"), Ok(())),
+                event(2, Operation::Commit { commit_ts: 2 }, Ok(())),
+                event(3, write("3"), Ok(())),
+                event(3, Operation::Commit { commit_ts: 0 }, Err(conflict)),
+                event(3, Operation::Abort, Ok(())),
+            ]
+        );
+        assert_eq!(history.check(), BTreeMap::new());
+    }
+
+    #[test]
+    fn test_lost_update() {
+        let history = lost_update(IsolationLevel::ReadCommitted);
+        assert_eq!(history.check(), anomaly(Anomaly::G2Item, &[3, 2]));
+    }
+
+    #[test]
+    fn test_write_skew() {
+        let write_skew = |isolation: IsolationLevel| {
+            let db = setup(IsolationLevel::ReadUncommitted, &[("x", "0"), ("y", "0")]);
+            let history = History::new();
+            let mut c2 = begin(&history, &db, isolation.clone());
+            let mut c3 = begin(&history, &db, isolation);
+            for c in [&mut c2, &mut c3] {
+                get(c, "x");
+                get(c, "y");
+            }
+            set(&mut c2, "x", "1");
+            set(&mut c3, "y", "1");
+            commit(&mut c2).unwrap();
+            let _ = commit(&mut c3);
+            history.check()
+        };
+        assert_eq!(
+            write_skew(IsolationLevel::Snapshot),
+            anomaly(Anomaly::G2Item, &[2, 3])
+        );
+        assert_eq!(write_skew(IsolationLevel::Serializable), BTreeMap::new());
+    }
+
+    #[test]
+    fn test_dirty_writes_and_reads() {
+        let db = setup(IsolationLevel::ReadUncommitted, &[("x", "0"), ("y", "0")]);
+        let history = History::new();
+        let mut c2 = begin(&history, &db, IsolationLevel::ReadUncommitted);
+        let mut c3 = begin(&history, &db, IsolationLevel::ReadUncommitted);
+        set(&mut c2, "x", "2");
+        set(&mut c3, "x", "3");
+        set(&mut c3, "y", "3");
+        set(&mut c2, "y", "2");
+        commit(&mut c2).unwrap();
+        commit(&mut c3).unwrap();
+        assert_eq!(history.check(), anomaly(Anomaly::G0, &[2, 3]));
+
+        let history = History::new();
+        let mut c4 = begin(&history, &db, IsolationLevel::ReadUncommitted);
+        let mut c5 = begin(&history, &db, IsolationLevel::ReadUncommitted);
+        let mut c6 = begin(&history, &db, IsolationLevel::ReadUncommitted);
+        set(&mut c4, "x", "4");
+        assert_eq!(get(&mut c5, "x"), Some("4".to_string()));
+        c4.exec_command(Command::Abort).unwrap();
+        commit(&mut c5).unwrap();
+        set(&mut c6, "y", "6");
+        let mut c7 = begin(&history, &db, IsolationLevel::ReadUncommitted);
+        assert_eq!(get(&mut c7, "y"), Some("6".to_string()));
+        set(&mut c6, "y", "66");
+        commit(&mut c6).unwrap();
+        commit(&mut c7).unwrap();
+        assert_eq!(
+            history.check(),
+            BTreeMap::from([(Anomaly::G1a, vec![5, 4]), (Anomaly::G1b, vec![7, 6])])
+        );
+    }
+
+    #[test]
+    fn test_circular_information_flow() {
+        let db = setup(IsolationLevel::ReadUncommitted, &[("x", "0"), ("y", "0")]);
+        let history = History::new();
+        let mut c2 = begin(&history, &db, IsolationLevel::ReadUncommitted);
+        let mut c3 = begin(&history, &db, IsolationLevel::ReadUncommitted);
+        set(&mut c2, "x", "2");
+        set(&mut c3, "y", "3");
+        assert_eq!(get(&mut c2, "y"), Some("3".to_string()));
+        assert_eq!(get(&mut c3, "x"), Some("2".to_string()));
+        commit(&mut c2).unwrap();
+        commit(&mut c3).unwrap();
+        assert_eq!(history.check(), anomaly(Anomaly::G1c, &[2, 3]));
+    }
+}
@@ -0,0 +1,179 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use rrmvcc::db::*;
+    use rrmvcc::lock::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::{thread, time::Duration};
+
+    fn set(c: &mut Connection, key: &str, val: &str) {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+    }
+
+    fn lock(c: &mut Connection, key: &str, mode: LockMode) -> Result<Option<String>, Error> {
+        let command = match mode {
+            LockMode::Shared => Command::GetForShare(key.to_string()),
+            LockMode::Exclusive => Command::GetForUpdate(key.to_string()),
+        };
+        match c.exec_command(command)? {
+            Response::Value { value, .. } => Ok(value),
+            ret => panic!("unexpected {:?}", ret),
+        }
+    }
+
+    fn begin(db: &Database, isolation: IsolationLevel) -> Connection {
+        let mut c = db.new_connection();
+        c.exec_command(Command::BeginWith(BeginOptions {
+            isolation: Some(isolation),
+            ..Default::default()
+        }))
+        .unwrap();
+        c
+    }
+
+    fn setup(policy: WaitPolicy) -> Database {
+        let mut db = common::setup(IsolationLevel::ReadCommitted, &[("x", "0")]);
+        db.lock_wait_policy = policy;
+        db
+    }
+
+    #[test]
+    fn test_shared_locks_are_compatible() {
+        let db = setup(WaitPolicy::NoWait);
+        let mut c1 = begin(&db, IsolationLevel::ReadCommitted);
+        let mut c2 = begin(&db, IsolationLevel::ReadCommitted);
+        assert_eq!(
+            lock(&mut c1, "x", LockMode::Shared),
+            Ok(Some("0".to_string()))
+        );
+        assert_eq!(
+            lock(&mut c2, "x", LockMode::Shared),
+            Ok(Some("0".to_string()))
+        );
+
+        let err = lock(&mut c1, "x", LockMode::Exclusive).unwrap_err();
+        assert_eq!(
+            err,
+            Error::LockConflict {
+                key: "x".to_string(),
+                holder: 3
+            }
+        );
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn test_exclusive_lock_until_commit_or_abort() {
+        let db = setup(WaitPolicy::NoWait);
+        let mut c1 = begin(&db, IsolationLevel::ReadCommitted);
+        let mut c2 = begin(&db, IsolationLevel::ReadCommitted);
+        lock(&mut c1, "x", LockMode::Exclusive).unwrap();
+        assert_eq!(
+            lock(&mut c2, "x", LockMode::Shared),
+            Err(Error::LockConflict {
+                key: "x".to_string(),
+                holder: 2
+            })
+        );
+        // Locks do not stop plain reads.
+        c2.exec_command(Command::Get("x".to_string())).unwrap();
+
+        set(&mut c1, "x", "1");
+        c1.exec_command(Command::Commit).unwrap();
+        assert!(db.locks.held(2).is_empty());
+        assert_eq!(
+            lock(&mut c2, "x", LockMode::Exclusive),
+            Ok(Some("1".to_string()))
+        );
+        c2.exec_command(Command::Abort).unwrap();
+        assert!(db.locks.held(3).is_empty());
+
+        let mut c3 = begin(&db, IsolationLevel::ReadCommitted);
+        lock(&mut c3, "x", LockMode::Exclusive).unwrap();
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let db = setup(WaitPolicy::NoWait);
+        let mut c = begin(&db, IsolationLevel::ReadCommitted);
+        lock(&mut c, "x", LockMode::Shared).unwrap();
+        assert_eq!(db.locks.held(2)["x"], LockMode::Shared);
+        lock(&mut c, "x", LockMode::Exclusive).unwrap();
+        assert_eq!(db.locks.held(2)["x"], LockMode::Exclusive);
+        lock(&mut c, "x", LockMode::Shared).unwrap();
+        assert_eq!(db.locks.held(2)["x"], LockMode::Exclusive);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let db = setup(WaitPolicy::Timeout(Duration::from_millis(20)));
+        let mut c1 = begin(&db, IsolationLevel::ReadCommitted);
+        let mut c2 = begin(&db, IsolationLevel::ReadCommitted);
+        lock(&mut c1, "x", LockMode::Exclusive).unwrap();
+        assert_eq!(
+            lock(&mut c2, "x", LockMode::Exclusive),
+            Err(Error::LockTimeout {
+                key: "x".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_stale_snapshot_fails_fast() {
+        let db = setup(WaitPolicy::NoWait);
+        let mut c1 = begin(&db, IsolationLevel::Snapshot);
+        let mut c2 = begin(&db, IsolationLevel::Snapshot);
+        lock(&mut c1, "x", LockMode::Exclusive).unwrap();
+        set(&mut c1, "x", "1");
+        c1.exec_command(Command::Commit).unwrap();
+        assert_eq!(
+            lock(&mut c2, "x", LockMode::Exclusive),
+            Err(Error::WriteWriteConflict {
+                key: "x".to_string(),
+                other_tx: 2
+            })
+        );
+        assert_eq!(
+            c2.exec_command(Command::Commit),
+            Err(Error::InvalidState {
+                tx_id: 3,
+                state: TransactionState::Aborted
+            })
+        );
+    }
+
+    #[test]
+    fn test_hot_counter_never_retries() {
+        let db = setup(WaitPolicy::Block);
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        let mut c = begin(&db, IsolationLevel::ReadCommitted);
+                        let n: u32 = lock(&mut c, "x", LockMode::Exclusive)
+                            .unwrap()
+                            .unwrap()
+                            .parse()
+                            .unwrap();
+                        set(&mut c, "x", &(n + 1).to_string());
+                        c.exec_command(Command::Commit).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut c = begin(&db, IsolationLevel::ReadCommitted);
+        assert_eq!(
+            lock(&mut c, "x", LockMode::Shared),
+            Ok(Some("160".to_string()))
+        );
+    }
+}
@@ -0,0 +1,163 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{get, setup};
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+
+    fn set(c: &mut Connection, key: &str, val: &str) {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+    }
+
+    fn begin(db: &Database, isolation: IsolationLevel) -> Connection {
+        begin_with(
+            db,
+            BeginOptions {
+                isolation: Some(isolation),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn begin_with(db: &Database, options: BeginOptions) -> Connection {
+        let mut c = db.new_connection();
+        c.exec_command(Command::BeginWith(options)).unwrap();
+        c
+    }
+
+    #[test]
+    fn test_each_connection_picks_its_level() {
+        let db = setup(IsolationLevel::ReadUncommitted, &[("x", "0")]);
+        let mut snapshot = begin(&db, IsolationLevel::Snapshot);
+        let mut read_committed = begin(&db, IsolationLevel::ReadCommitted);
+        let mut default = db.new_connection();
+        default.exec_command(Command::Begin).unwrap();
+        assert_eq!(
+            default.tx.as_ref().unwrap().read().unwrap().isolation_level,
+            IsolationLevel::ReadUncommitted
+        );
+
+        let mut writer = begin(&db, IsolationLevel::Snapshot);
+        set(&mut writer, "x", "1");
+        assert_eq!(get(&mut default, "x"), Some("1".to_string()));
+        assert_eq!(get(&mut read_committed, "x"), Some("0".to_string()));
+        assert_eq!(get(&mut snapshot, "x"), Some("0".to_string()));
+
+        writer.exec_command(Command::Commit).unwrap();
+        assert_eq!(get(&mut default, "x"), Some("1".to_string()));
+        assert_eq!(get(&mut read_committed, "x"), Some("1".to_string()));
+        assert_eq!(get(&mut snapshot, "x"), Some("0".to_string()));
+    }
+
+    #[test]
+    fn test_write_conflicts_across_levels() {
+        let db = setup(IsolationLevel::ReadUncommitted, &[("x", "0")]);
+
+        // A Snapshot transaction loses to a Read Committed one that committed
+        // first.
+        let mut snapshot = begin(&db, IsolationLevel::Snapshot);
+        let mut read_committed = begin(&db, IsolationLevel::ReadCommitted);
+        set(&mut snapshot, "x", "1");
+        set(&mut read_committed, "x", "2");
+        read_committed.exec_command(Command::Commit).unwrap();
+        assert!(matches!(
+            snapshot.exec_command(Command::Commit),
+            Err(Error::WriteWriteConflict { other_tx: 3, .. })
+        ));
+
+        // Read Committed does not check anything itself.
+        let mut snapshot = begin(&db, IsolationLevel::Snapshot);
+        let mut read_committed = begin(&db, IsolationLevel::ReadCommitted);
+        set(&mut snapshot, "x", "3");
+        set(&mut read_committed, "x", "4");
+        snapshot.exec_command(Command::Commit).unwrap();
+        read_committed.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_serializable_pivot_with_snapshot_writer() {
+        let db = setup(IsolationLevel::ReadUncommitted, &[("x", "0"), ("y", "0")]);
+
+        // reader -rw-> pivot -rw-> writer, where only the writer is Snapshot.
+        let mut reader = begin(&db, IsolationLevel::Serializable);
+        let mut pivot = begin(&db, IsolationLevel::Serializable);
+        let mut writer = begin(&db, IsolationLevel::Snapshot);
+        get(&mut reader, "y");
+        set(&mut reader, "z", "1");
+        get(&mut pivot, "x");
+        set(&mut pivot, "y", "1");
+        set(&mut writer, "x", "1");
+        writer.exec_command(Command::Commit).unwrap();
+        assert!(matches!(
+            pivot.exec_command(Command::Commit),
+            Err(Error::ReadWriteConflict { other_tx: 4, .. })
+        ));
+        reader.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_declared_read_only_reader_does_not_doom_the_pivot() {
+        let db = setup(IsolationLevel::ReadUncommitted, &[("x", "0"), ("y", "0")]);
+
+        // The reader's snapshot predates the writer's commit, so it can be
+        // ordered before all of them.
+        let mut reader = begin_with(
+            &db,
+            BeginOptions {
+                isolation: Some(IsolationLevel::Serializable),
+                read_only: true,
+                ..Default::default()
+            },
+        );
+        let mut pivot = begin(&db, IsolationLevel::Serializable);
+        let mut writer = begin(&db, IsolationLevel::Snapshot);
+        get(&mut reader, "y");
+        get(&mut pivot, "x");
+        set(&mut pivot, "y", "1");
+        set(&mut writer, "x", "1");
+        writer.exec_command(Command::Commit).unwrap();
+        pivot.exec_command(Command::Commit).unwrap();
+        reader.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_only_serializable_reads_are_tracked() {
+        let db = setup(IsolationLevel::ReadUncommitted, &[("x", "0"), ("y", "0")]);
+
+        // Write skew where one side is Snapshot: like PostgreSQL, Serializable
+        // only guarantees serializability among Serializable transactions.
+        let mut serializable = begin(&db, IsolationLevel::Serializable);
+        let mut snapshot = begin(&db, IsolationLevel::Snapshot);
+        get(&mut serializable, "x");
+        get(&mut snapshot, "y");
+        set(&mut serializable, "y", "1");
+        set(&mut snapshot, "x", "1");
+        snapshot.exec_command(Command::Commit).unwrap();
+        serializable.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_read_only_transactions_reject_writes() {
+        let db = setup(IsolationLevel::ReadUncommitted, &[("x", "0")]);
+        let mut c = begin_with(
+            &db,
+            BeginOptions {
+                read_only: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            c.exec_command(Command::Set("x".to_string(), "1".to_string())),
+            Err(Error::ReadOnly(2))
+        );
+        assert_eq!(
+            c.exec_command(Command::Delete("x".to_string())),
+            Err(Error::ReadOnly(2))
+        );
+        assert_eq!(get(&mut c, "x"), Some("0".to_string()));
+        c.exec_command(Command::Commit).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use rrmvcc::db::*;
+    use rrmvcc::model::*;
+    use rrmvcc::sim::*;
+    use rrmvcc::tx::*;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    const KEYS: [&str; 3] = ["a", "b", "c"];
+
+    const INITIAL: [(&str, &str); 2] = [("a", "0"), ("b", "0")];
+
+    fn initial() -> BTreeMap<String, String> {
+        INITIAL
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn setup(level: IsolationLevel) -> impl Fn() -> Database {
+        move || common::setup(level.clone(), &INITIAL)
+    }
+
+    /// Two or three connections, each running one transaction of up to four
+    /// reads, writes, deletes and scans. Every write has its own value.
+    fn workload(rng: &mut Rng) -> Vec<Vec<Command>> {
+        let connections = 2 + rng.below(2);
+        (0..connections)
+            .map(|c| {
+                let mut script = vec![Command::Begin];
+                for i in 0..1 + rng.below(4) {
+                    let key = KEYS[rng.below(KEYS.len())].to_string();
+                    script.push(match rng.below(5) {
+                        0 | 1 => Command::Get(key),
+                        2 => Command::Set(key, format!("{}.{}", c, i)),
+                        3 => Command::Delete(key),
+                        _ => Command::Scan {
+                            start: Bound::Included(key),
+                            end: Bound::Unbounded,
+                            limit: None,
+                            reverse: false,
+                        },
+                    });
+                }
+                script.push(Command::Commit);
+                script
+            })
+            .collect()
+    }
+
+    /// Checks `workloads` random workloads in `runs` random interleavings
+    /// each, returning the first failure with the seed of its workload.
+    fn check(level: IsolationLevel, workloads: u64, runs: usize) -> Result<(), String> {
+        for seed in 0..workloads {
+            let sim = Simulation::new(workload(&mut Rng::new(seed))).setup(setup(level.clone()));
+            if let Err(failure) = sim.check_random(seed, runs, serial(initial())) {
+                return Err(format!("workload {}: {}", seed, failure));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_model() {
+        let mut model = Model::new(initial());
+        let responses = model.run(
+            7,
+            &[
+                Command::Begin,
+                Command::Set("c".to_string(), "1".to_string()),
+                Command::Savepoint("s".to_string()),
+                Command::Delete("a".to_string()),
+                Command::RollbackTo("s".to_string()),
+                Command::Scan {
+                    start: Bound::Excluded("a".to_string()),
+                    end: Bound::Unbounded,
+                    limit: Some(1),
+                    reverse: true,
+                },
+                Command::Get("a".to_string()),
+                Command::Commit,
+            ],
+        );
+        assert_eq!(responses[0], None);
+        assert_eq!(
+            responses[5],
+            Some(Response::Rows {
+                tx_id: 7,
+                rows: vec![("c".to_string(), "1".to_string())]
+            })
+        );
+        assert_eq!(
+            responses[6],
+            Some(Response::Value {
+                tx_id: 7,
+                key: "a".to_string(),
+                value: Some("0".to_string())
+            })
+        );
+        assert_eq!(model.data.len(), 3);
+    }
+
+    #[test]
+    fn test_committed_delete_is_not_undone() {
+        // The first workload that failed: a write to a key another
+        // transaction deleted and committed must not bring it back.
+        let sim = Simulation::new(vec![
+            vec![
+                Command::Begin,
+                Command::Delete("a".to_string()),
+                Command::Commit,
+            ],
+            vec![
+                Command::Begin,
+                Command::Set("a".to_string(), "1".to_string()),
+                Command::Commit,
+            ],
+        ])
+        .setup(setup(IsolationLevel::Serializable));
+        let run = sim.run(&sim.interleave(&[1, 0, 0, 0, 1, 1]));
+        assert!(run.results[5].is_err());
+        serial(initial())(&run).unwrap();
+    }
+
+    #[test]
+    fn test_serializable_matches_a_serial_order() {
+        check(IsolationLevel::Serializable, 60, 20).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_does_not() {
+        let failure = check(IsolationLevel::Snapshot, 60, 20).unwrap_err();
+        assert!(failure.contains("no serial order"), "{}", failure);
+    }
+}
@@ -0,0 +1,158 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::observe::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::sync::{Arc, Mutex};
+
+    /// A database at `level` whose events end up in the returned list.
+    fn observed(level: IsolationLevel) -> (Database, Arc<Mutex<Vec<Event>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut db = Database::new();
+        db.default_isolation_level = level;
+        let sink = Arc::clone(&events);
+        db.observer = Some(Arc::new(move |event: &Event| {
+            sink.lock().unwrap().push(event.clone())
+        }));
+        (db, events)
+    }
+
+    #[test]
+    fn test_events() {
+        let (db, events) = observed(IsolationLevel::Snapshot);
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c.exec_command(Command::Set("x".to_string(), "1".to_string()))
+            .unwrap();
+        c.exec_command(Command::Get("x".to_string())).unwrap();
+        c.exec_command(Command::Commit).unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events[0],
+            Event::Begin {
+                tx_id: 1,
+                isolation: IsolationLevel::Snapshot
+            }
+        );
+        assert_eq!(
+            events[1],
+            Event::Command {
+                tx_id: Some(1),
+                command: Command::Begin,
+                outcome: Ok(Response::Begun { tx_id: 1 })
+            }
+        );
+        assert_eq!(
+            events[3],
+            Event::Visibility {
+                tx_id: 1,
+                key: Some("x".to_string()),
+                tx_start_id: 1,
+                tx_end_id: 0,
+                visible: true
+            }
+        );
+        assert_eq!(
+            events[5],
+            Event::Complete {
+                tx_id: 1,
+                state: TransactionState::Committed,
+                outcome: Ok(())
+            }
+        );
+        assert_eq!(events.len(), 7);
+    }
+
+    #[test]
+    fn test_visibility_matches_the_read() {
+        let (db, events) = observed(IsolationLevel::ReadUncommitted);
+        let mut c1 = db.new_connection();
+        let mut c2 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        c2.exec_command(Command::Begin).unwrap();
+        c1.exec_command(Command::Set("x".to_string(), "1".to_string()))
+            .unwrap();
+        c2.exec_command(Command::Set("x".to_string(), "2".to_string()))
+            .unwrap();
+        events.lock().unwrap().clear();
+        c1.exec_command(Command::Get("x".to_string())).unwrap();
+
+        // c1 reads its own write, not c2's newer one it could otherwise see.
+        let visible: Vec<_> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                Event::Visibility {
+                    tx_start_id,
+                    visible,
+                    ..
+                } => Some((*tx_start_id, *visible)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(visible, [(1, true), (2, false)]);
+    }
+
+    #[test]
+    fn test_failed_commit() {
+        let (db, events) = observed(IsolationLevel::Snapshot);
+        let mut c1 = db.new_connection();
+        let mut c2 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        c2.exec_command(Command::Begin).unwrap();
+        c1.exec_command(Command::Set("x".to_string(), "1".to_string()))
+            .unwrap();
+        c2.exec_command(Command::Set("x".to_string(), "2".to_string()))
+            .unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+        events.lock().unwrap().clear();
+        assert!(c2.exec_command(Command::Commit).is_err());
+
+        let conflict = Error::WriteWriteConflict {
+            key: "x".to_string(),
+            other_tx: 1,
+        };
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                Event::Complete {
+                    tx_id: 2,
+                    state: TransactionState::Committed,
+                    outcome: Err(conflict.clone())
+                },
+                Event::Command {
+                    tx_id: Some(2),
+                    command: Command::Commit,
+                    outcome: Err(conflict)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observer_can_use_the_database() {
+        let mut db = Database::new();
+        let handle = db.clone();
+        let versions = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&versions);
+        db.observer = Some(Arc::new(move |event: &Event| {
+            if let Event::Complete { .. } = event {
+                seen.lock().unwrap().push(handle.stats().versions);
+            }
+        }));
+
+        let tx = db.new_transaction();
+        let val = Value {
+            data: "1".to_string(),
+            tx_start_id: 1,
+            tx_end_id: 0,
+        };
+        assert!(db.is_visible(&tx, &val));
+        db.complete_transaction(1, TransactionState::Aborted)
+            .unwrap();
+        assert_eq!(*versions.lock().unwrap(), [0]);
+    }
+}
@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::parse::*;
+    use rrmvcc::tx::*;
+    use std::ops::Bound;
+
+    fn parse(line: &str) -> Result<Command, ParseError> {
+        line.parse()
+    }
+
+    fn key(key: &str) -> String {
+        key.to_string()
+    }
+
+    #[test]
+    fn test_basic_commands() {
+        assert_eq!(parse("BEGIN"), Ok(Command::Begin));
+        assert_eq!(parse("  get x "), Ok(Command::Get(key("x"))));
+        assert_eq!(
+            parse("SET x hey there"),
+            Ok(Command::Set(key("x"), "hey there".to_string()))
+        );
+        assert_eq!(parse("DELETE x"), Ok(Command::Delete(key("x"))));
+        assert_eq!(parse("del x"), Ok(Command::Delete(key("x"))));
+        assert_eq!(parse("COMMIT"), Ok(Command::Commit));
+        assert_eq!(parse("ABORT"), Ok(Command::Abort));
+        assert_eq!(parse("rollback"), Ok(Command::Abort));
+    }
+
+    #[test]
+    fn test_begin_options() {
+        assert_eq!(
+            parse("BEGIN ISOLATION read_committed"),
+            Ok(Command::BeginWith(BeginOptions {
+                isolation: Some(IsolationLevel::ReadCommitted),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            parse("begin isolation level Serializable read only"),
+            Ok(Command::BeginWith(BeginOptions {
+                isolation: Some(IsolationLevel::Serializable),
+                read_only: true,
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            parse("BEGIN AS OF 3"),
+            Ok(Command::BeginWith(BeginOptions {
+                as_of: Some(3),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            "REPEATABLE-READ".parse::<IsolationLevel>(),
+            Ok(IsolationLevel::RepeatableRead)
+        );
+    }
+
+    #[test]
+    fn test_locks_scans_and_savepoints() {
+        assert_eq!(
+            parse("GET x FOR UPDATE"),
+            Ok(Command::GetForUpdate(key("x")))
+        );
+        assert_eq!(parse("GET x for share"), Ok(Command::GetForShare(key("x"))));
+        assert_eq!(
+            parse("SCAN"),
+            Ok(Command::Scan {
+                start: Bound::Unbounded,
+                end: Bound::Unbounded,
+                limit: None,
+                reverse: false
+            })
+        );
+        assert_eq!(
+            parse("SCAN a c LIMIT 2 REVERSE"),
+            Ok(Command::Scan {
+                start: Bound::Included(key("a")),
+                end: Bound::Excluded(key("c")),
+                limit: Some(2),
+                reverse: true
+            })
+        );
+        assert_eq!(
+            parse("PREFIX user: LIMIT 10"),
+            Ok(Command::ScanPrefix {
+                prefix: key("user:"),
+                limit: Some(10),
+                reverse: false
+            })
+        );
+        assert_eq!(parse("SAVEPOINT a"), Ok(Command::Savepoint(key("a"))));
+        assert_eq!(parse("ROLLBACK TO a"), Ok(Command::RollbackTo(key("a"))));
+        assert_eq!(
+            parse("ROLLBACK TO SAVEPOINT a"),
+            Ok(Command::RollbackTo(key("a")))
+        );
+        assert_eq!(parse("RELEASE a"), Ok(Command::Release(key("a"))));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |message: &str| Err(ParseError(message.to_string()));
+        assert_eq!(parse(""), err("expected a command"));
+        assert_eq!(parse("FROB x"), err("unknown command FROB"));
+        assert_eq!(parse("GET"), err("expected key"));
+        assert_eq!(parse("SET x"), err("expected value"));
+        assert_eq!(parse("GET x y"), err("unexpected y"));
+        assert_eq!(parse("GET x FOR"), err("expected UPDATE or SHARE"));
+        assert_eq!(
+            parse("BEGIN ISOLATION chaos"),
+            err("unknown isolation level chaos")
+        );
+        assert_eq!(parse("SCAN LIMIT many"), err("invalid limit many"));
+    }
+
+    #[test]
+    fn test_generic_keys() {
+        let command: Result<Command<u32, i64>, _> = "SET 7 -3".parse();
+        assert_eq!(command, Ok(Command::Set(7, -3)));
+        let command: Result<Command<u32, i64>, _> = "GET seven".parse();
+        assert_eq!(command, Err(ParseError("invalid key seven".to_string())));
+    }
+}
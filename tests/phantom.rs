@@ -0,0 +1,143 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::setup;
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::ops::Bound;
+
+    fn set(c: &mut Connection, key: &str, val: &str) {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+    }
+
+    fn count(c: &mut Connection, prefix: &str) -> usize {
+        match c.exec_command(Command::ScanPrefix {
+            prefix: prefix.to_string(),
+            limit: None,
+            reverse: false,
+        }) {
+            Ok(Response::Rows { rows, .. }) => rows.len(),
+            ret => panic!("unexpected {:?}", ret),
+        }
+    }
+
+    const ORDERS: &[(&str, &str)] = &[("order/1", "10"), ("order/2", "20")];
+
+    #[test]
+    fn test_phantom_inserts_abort_serializable_scan() {
+        let db = setup(IsolationLevel::Serializable, ORDERS);
+
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        // Both enforce "at most three orders", and both insert the third.
+        assert_eq!(count(&mut c1, "order/"), 2);
+        assert_eq!(count(&mut c2, "order/"), 2);
+        set(&mut c1, "order/3", "30");
+        set(&mut c2, "order/4", "40");
+        c1.exec_command(Command::Commit).unwrap();
+
+        // c2 sees its own order but not c1's.
+        assert_eq!(count(&mut c2, "order/"), 3);
+        assert_eq!(
+            c2.exec_command(Command::Commit),
+            Err(Error::ReadWriteConflict {
+                key: "order/3".to_string(),
+                other_tx: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_phantom_delete_aborts_serializable_scan() {
+        let db = setup(IsolationLevel::Serializable, ORDERS);
+
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        // A limited scan still guards the whole range it was asked for.
+        let scan = c1
+            .exec_command(Command::Scan {
+                start: Bound::Included("order/".to_string()),
+                end: Bound::Excluded("order/9".to_string()),
+                limit: Some(1),
+                reverse: false,
+            })
+            .unwrap();
+        assert!(matches!(scan, Response::Rows { rows, .. } if rows.len() == 1));
+        assert_eq!(count(&mut c2, "order/"), 2);
+        c1.exec_command(Command::Delete("order/2".to_string()))
+            .unwrap();
+        set(&mut c2, "order/3", "30");
+        c1.exec_command(Command::Commit).unwrap();
+
+        assert_eq!(
+            c2.exec_command(Command::Commit),
+            Err(Error::ReadWriteConflict {
+                key: "order/2".to_string(),
+                other_tx: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_phantom_without_cycle_commits() {
+        let db = setup(IsolationLevel::Serializable, ORDERS);
+
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        // c1 simply serializes before c2.
+        assert_eq!(count(&mut c1, "order/"), 2);
+        set(&mut c2, "order/3", "30");
+        c2.exec_command(Command::Commit).unwrap();
+        assert_eq!(count(&mut c1, "order/"), 2);
+        set(&mut c1, "summary", "2");
+        c1.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_writes_outside_scanned_range_commit() {
+        let db = setup(IsolationLevel::Serializable, ORDERS);
+
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        assert_eq!(count(&mut c1, "order/"), 2);
+        set(&mut c2, "orders", "30");
+        set(&mut c2, "order", "30");
+        c2.exec_command(Command::Commit).unwrap();
+
+        set(&mut c1, "summary", "2");
+        c1.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_phantom_allowed_below_serializable() {
+        let db = setup(IsolationLevel::Snapshot, ORDERS);
+
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        assert_eq!(count(&mut c1, "order/"), 2);
+        set(&mut c2, "order/3", "30");
+        c2.exec_command(Command::Commit).unwrap();
+
+        assert_eq!(count(&mut c1, "order/"), 2);
+        set(&mut c1, "summary", "2");
+        c1.exec_command(Command::Commit).unwrap();
+    }
+}
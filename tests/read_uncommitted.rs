@@ -14,16 +14,31 @@ mod tests {
         let mut c2 = db.new_connection();
         c2.exec_command(Command::Begin).unwrap();
 
-        if let Ok(ret) = c1.exec_command(Command::Set("hello".to_string(), "world".to_string())) {
-            assert_eq!(ret, "[SET] key:hello, val:world");
-        }
+        assert_eq!(
+            c1.exec_command(Command::Set("hello".to_string(), "world".to_string())),
+            Ok(Response::Written {
+                tx_id: 1,
+                key: "hello".to_string(),
+                value: "world".to_string()
+            })
+        );
 
-        if let Ok(ret) = c1.exec_command(Command::Get("hello".to_string())) {
-            assert_eq!(ret, "[GET] key:hello, val:world");
-        }
+        assert_eq!(
+            c1.exec_command(Command::Get("hello".to_string())),
+            Ok(Response::Value {
+                tx_id: 1,
+                key: "hello".to_string(),
+                value: Some("world".to_string())
+            })
+        );
 
-        if let Ok(ret) = c2.exec_command(Command::Get("hello".to_string())) {
-            assert_eq!(ret, "[GET] key:hello, val:world");
-        }
+        assert_eq!(
+            c2.exec_command(Command::Get("hello".to_string())),
+            Ok(Response::Value {
+                tx_id: 2,
+                key: "hello".to_string(),
+                value: Some("world".to_string())
+            })
+        );
     }
 }
@@ -14,69 +14,126 @@ mod tests {
         let mut c2 = db.new_connection();
         c2.exec_command(Command::Begin).unwrap();
 
-        if let Ok(ret) = c1.exec_command(Command::Set("x".to_string(), "hey".to_string())) {
-            assert_eq!(ret, "[SET] key:x, val:hey");
-        }
-
-        if let Err(ret) = c2.exec_command(Command::Get("x".to_string())) {
-            assert_eq!(ret, "[GET] key x not found");
-        }
-
-        if let Ok(ret) = c1.exec_command(Command::Commit) {
-            assert_eq!(ret, "[COMMIT] finish");
-        }
-
-        if let Err(ret) = c2.exec_command(Command::Get("x".to_string())) {
-            assert_eq!(ret, "[GET] key x not found");
-        }
+        assert_eq!(
+            c1.exec_command(Command::Set("x".to_string(), "hey".to_string())),
+            Ok(Response::Written {
+                tx_id: 1,
+                key: "x".to_string(),
+                value: "hey".to_string()
+            })
+        );
+
+        assert_eq!(
+            c2.exec_command(Command::Get("x".to_string())),
+            Ok(Response::Value {
+                tx_id: 2,
+                key: "x".to_string(),
+                value: None
+            })
+        );
+
+        assert_eq!(
+            c1.exec_command(Command::Commit),
+            Ok(Response::Committed { tx_id: 1 })
+        );
+
+        assert_eq!(
+            c2.exec_command(Command::Get("x".to_string())),
+            Ok(Response::Value {
+                tx_id: 2,
+                key: "x".to_string(),
+                value: None
+            })
+        );
 
         let mut c3 = db.new_connection();
         c3.exec_command(Command::Begin).unwrap();
 
-        if let Ok(ret) = c3.exec_command(Command::Get("x".to_string())) {
-            assert_eq!(ret, "[GET] key:x, val:hey")
-        }
-
-        if let Ok(ret) = c3.exec_command(Command::Set("x".to_string(), "yall".to_string())) {
-            assert_eq!(ret, "[SET] key:x, val:yall");
-        }
-
-        if let Ok(ret) = c3.exec_command(Command::Get("x".to_string())) {
-            assert_eq!(ret, "[GET] key:x, val:yall")
-        }
-
-        if let Err(ret) = c2.exec_command(Command::Get("x".to_string())) {
-            assert_eq!(ret, "[GET] key x not found");
-        }
-
-        if let Ok(ret) = c3.exec_command(Command::Abort) {
-            assert_eq!(ret, "[ABORT] finish");
-        }
-
-        if let Err(ret) = c2.exec_command(Command::Get("x".to_string())) {
-            assert_eq!(ret, "[GET] key x not found");
-        }
+        assert_eq!(
+            c3.exec_command(Command::Get("x".to_string())),
+            Ok(Response::Value {
+                tx_id: 3,
+                key: "x".to_string(),
+                value: Some("hey".to_string())
+            })
+        );
+
+        assert_eq!(
+            c3.exec_command(Command::Set("x".to_string(), "yall".to_string())),
+            Ok(Response::Written {
+                tx_id: 3,
+                key: "x".to_string(),
+                value: "yall".to_string()
+            })
+        );
+
+        assert_eq!(
+            c3.exec_command(Command::Get("x".to_string())),
+            Ok(Response::Value {
+                tx_id: 3,
+                key: "x".to_string(),
+                value: Some("yall".to_string())
+            })
+        );
+
+        assert_eq!(
+            c2.exec_command(Command::Get("x".to_string())),
+            Ok(Response::Value {
+                tx_id: 2,
+                key: "x".to_string(),
+                value: None
+            })
+        );
+
+        assert_eq!(
+            c3.exec_command(Command::Abort),
+            Ok(Response::Aborted { tx_id: 3 })
+        );
+
+        assert_eq!(
+            c2.exec_command(Command::Get("x".to_string())),
+            Ok(Response::Value {
+                tx_id: 2,
+                key: "x".to_string(),
+                value: None
+            })
+        );
 
         let mut c4 = db.new_connection();
         c4.exec_command(Command::Begin).unwrap();
 
-        if let Ok(ret) = c4.exec_command(Command::Get("x".to_string())) {
-            assert_eq!(ret, "[GET] key:x, val:hey")
-        }
-
-        if let Ok(ret) = c4.exec_command(Command::Delete("x".to_string())) {
-            assert_eq!(ret, "[DELETE] key:x");
-        }
-
-        if let Ok(ret) = c4.exec_command(Command::Commit) {
-            assert_eq!(ret, "[COMMIT] finish");
-        }
+        assert_eq!(
+            c4.exec_command(Command::Get("x".to_string())),
+            Ok(Response::Value {
+                tx_id: 4,
+                key: "x".to_string(),
+                value: Some("hey".to_string())
+            })
+        );
+
+        assert_eq!(
+            c4.exec_command(Command::Delete("x".to_string())),
+            Ok(Response::Deleted {
+                tx_id: 4,
+                key: "x".to_string()
+            })
+        );
+
+        assert_eq!(
+            c4.exec_command(Command::Commit),
+            Ok(Response::Committed { tx_id: 4 })
+        );
 
         let mut c5 = db.new_connection();
         c5.exec_command(Command::Begin).unwrap();
 
-        if let Err(ret) = c5.exec_command(Command::Get("x".to_string())) {
-            assert_eq!(ret, "[GET] key x not found");
-        }
+        assert_eq!(
+            c5.exec_command(Command::Get("x".to_string())),
+            Ok(Response::Value {
+                tx_id: 5,
+                key: "x".to_string(),
+                value: None
+            })
+        );
     }
 }
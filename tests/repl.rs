@@ -0,0 +1,82 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::repl::*;
+
+    fn run(repl: &mut Repl, lines: &[&str]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| repl.exec_line(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_named_connections() {
+        let mut repl = Repl::new(Database::new());
+        assert_eq!(repl.prompt(), "c1> ");
+        let output = run(
+            &mut repl,
+            &[
+                "BEGIN ISOLATION snapshot",
+                "SET x hey",
+                "\\c c2",
+                "BEGIN ISOLATION snapshot",
+                "GET x",
+                "\\c c1",
+                "COMMIT",
+                "\\c c2",
+                "GET x",
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                "[BEGIN] finish",
+                "[SET] key:x, val:hey",
+                "connected to c2",
+                "[BEGIN] finish",
+                "[GET] key x not found",
+                "connected to c1",
+                "[COMMIT] finish",
+                "connected to c2",
+                "[GET] key x not found",
+            ]
+        );
+        assert_eq!(repl.prompt(), "c2> ");
+        assert_eq!(
+            run(&mut repl, &["\\l"]),
+            ["  c1 tx 1 Snapshot Committed\n* c2 tx 2 Snapshot Active"]
+        );
+    }
+
+    #[test]
+    fn test_errors_and_quit() {
+        let mut repl = Repl::new(Database::new());
+        assert_eq!(
+            run(&mut repl, &["", "GET x", "FROB", "\\x"]),
+            [
+                "",
+                "ERROR: no active transaction",
+                "ERROR: unknown command FROB",
+                "ERROR: unknown meta command \\x",
+            ]
+        );
+        assert_eq!(repl.exec_line("\\q"), None);
+    }
+
+    #[test]
+    fn test_lock_conflicts_fail_at_once() {
+        let mut repl = Repl::new(Database::new());
+        let output = run(
+            &mut repl,
+            &[
+                "BEGIN",
+                "GET x FOR UPDATE",
+                "\\c c2",
+                "BEGIN",
+                "GET x FOR UPDATE",
+            ],
+        );
+        assert_eq!(output[4], "ERROR: key x is locked by transaction 1");
+    }
+}
@@ -0,0 +1,216 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::resp::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        /// Sends `args` as an array of bulk strings and returns the raw reply.
+        fn call(&mut self, args: &[&str]) -> String {
+            let mut request = format!("*{}\r\n", args.len());
+            for arg in args {
+                request += &format!("${}\r\n{}\r\n", arg.len(), arg);
+            }
+            self.writer.write_all(request.as_bytes()).unwrap();
+            self.read_reply()
+        }
+
+        fn read_reply(&mut self) -> String {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            let mut reply = line.clone();
+            let len = line[1..].trim_end().parse::<i64>().unwrap_or(-1);
+            match line.as_bytes()[0] {
+                b'$' if len >= 0 => {
+                    let mut data = vec![0; len as usize + 2];
+                    self.reader.read_exact(&mut data).unwrap();
+                    reply += &String::from_utf8(data).unwrap();
+                }
+                b'*' => {
+                    for _ in 0..len {
+                        reply += &self.read_reply();
+                    }
+                }
+                _ => {}
+            }
+            reply
+        }
+    }
+
+    fn start(db: Database) -> impl Fn() -> Client {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, db));
+        move || {
+            let writer = TcpStream::connect(addr).unwrap();
+            Client {
+                reader: BufReader::new(writer.try_clone().unwrap()),
+                writer,
+            }
+        }
+    }
+
+    #[test]
+    fn test_autocommit() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::ReadCommitted;
+        let connect = start(db);
+        let mut c1 = connect();
+        let mut c2 = connect();
+        assert_eq!(c1.call(&["PING"]), "+PONG\r\n");
+        assert_eq!(c1.call(&["SET", "x", "hey there"]), "+OK\r\n");
+        assert_eq!(c2.call(&["get", "x"]), "$9\r\nhey there\r\n");
+        assert_eq!(c2.call(&["GET", "y"]), "$-1\r\n");
+        assert_eq!(c2.call(&["DEL", "x", "y"]), ":1\r\n");
+        assert_eq!(c1.call(&["GET", "x"]), "$-1\r\n");
+        assert_eq!(
+            c1.call(&["GET"]),
+            "-ERR unknown command or wrong number of arguments for GET\r\n"
+        );
+    }
+
+    #[test]
+    fn test_multi_exec_discard() {
+        let connect = start(Database::new());
+        let mut c1 = connect();
+        let mut c2 = connect();
+        assert_eq!(c1.call(&["MULTI"]), "+OK\r\n");
+        assert_eq!(c1.call(&["SET", "x", "1"]), "+QUEUED\r\n");
+        assert_eq!(c1.call(&["GET", "x"]), "+QUEUED\r\n");
+        assert_eq!(c2.call(&["GET", "x"]), "$-1\r\n");
+        assert_eq!(c1.call(&["EXEC"]), "*2\r\n+OK\r\n$1\r\n1\r\n");
+        assert_eq!(c2.call(&["GET", "x"]), "$1\r\n1\r\n");
+
+        assert_eq!(c1.call(&["MULTI"]), "+OK\r\n");
+        assert_eq!(c1.call(&["SET", "x", "2"]), "+QUEUED\r\n");
+        assert_eq!(c1.call(&["DISCARD"]), "+OK\r\n");
+        assert_eq!(c1.call(&["GET", "x"]), "$1\r\n1\r\n");
+        assert_eq!(c1.call(&["EXEC"]), "-ERR EXEC without MULTI\r\n");
+    }
+
+    #[test]
+    fn test_begin_isolation_and_conflicts() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::ReadCommitted;
+        let connect = start(db);
+        let mut c1 = connect();
+        let mut c2 = connect();
+        c1.call(&["SET", "x", "0"]);
+
+        assert_eq!(c1.call(&["BEGIN", "ISOLATION", "snapshot"]), "+OK\r\n");
+        assert_eq!(c2.call(&["BEGIN", "ISOLATION", "snapshot"]), "+OK\r\n");
+        assert_eq!(c1.call(&["SET", "x", "1"]), "+OK\r\n");
+        assert_eq!(c2.call(&["SET", "x", "2"]), "+OK\r\n");
+        assert_eq!(c2.call(&["GET", "x"]), "$1\r\n2\r\n");
+        assert_eq!(c1.call(&["COMMIT"]), "+OK\r\n");
+        assert_eq!(
+            c2.call(&["COMMIT"]),
+            "-CONFLICT write-write conflict on key x with transaction 2\r\n"
+        );
+        assert_eq!(c2.call(&["GET", "x"]), "$1\r\n1\r\n");
+
+        assert_eq!(
+            c1.call(&["BEGIN", "ISOLATION", "chaos"]),
+            "-ERR unknown isolation level chaos\r\n"
+        );
+        assert_eq!(c1.call(&["ROLLBACK"]), "+OK\r\n");
+    }
+
+    #[test]
+    fn test_exec_conflict_and_inline_commands() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+        db.write_conflicts = WriteConflicts::FailFast;
+        let connect = start(db);
+        let mut c1 = connect();
+        let mut c2 = connect();
+
+        c1.call(&["BEGIN"]);
+        c1.call(&["SET", "x", "1"]);
+        c2.call(&["MULTI"]);
+        c2.call(&["SET", "y", "1"]);
+        c2.call(&["SET", "x", "2"]);
+        assert_eq!(
+            c2.call(&["EXEC"]),
+            "-CONFLICT write-write conflict on key x with transaction 1\r\n"
+        );
+        assert_eq!(c1.call(&["COMMIT"]), "+OK\r\n");
+        assert_eq!(c1.call(&["GET", "y"]), "$-1\r\n");
+
+        // As typed into telnet.
+        c1.writer.write_all(b"GET x\r\nQUIT\r\n").unwrap();
+        assert_eq!(c1.read_reply(), "$1\r\n1\r\n");
+        assert_eq!(c1.read_reply(), "+OK\r\n");
+        let mut rest = String::new();
+        c1.reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "");
+    }
+
+    #[test]
+    fn test_aborting_failure_ends_begin() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+        db.write_conflicts = WriteConflicts::FailFast;
+        let connect = start(db);
+        let mut c1 = connect();
+        let mut c2 = connect();
+        c1.call(&["BEGIN"]);
+        c1.call(&["SET", "x", "1"]);
+
+        c2.call(&["BEGIN"]);
+        assert_eq!(
+            c2.call(&["SET", "x", "2"]),
+            "-CONFLICT write-write conflict on key x with transaction 1\r\n"
+        );
+        assert_eq!(c2.call(&["SET", "y", "1"]), "+OK\r\n");
+        assert_eq!(c2.call(&["GET", "y"]), "$1\r\n1\r\n");
+
+        c2.call(&["BEGIN"]);
+        c2.call(&["MULTI"]);
+        c2.call(&["SET", "x", "3"]);
+        assert_eq!(
+            c2.call(&["EXEC"]),
+            "-CONFLICT write-write conflict on key x with transaction 1\r\n"
+        );
+        assert_eq!(c2.call(&["SET", "y", "2"]), "+OK\r\n");
+        assert_eq!(c2.call(&["GET", "y"]), "$1\r\n2\r\n");
+    }
+
+    #[test]
+    fn test_oversized_lengths_drop_only_that_client() {
+        let connect = start(Database::new());
+        for (header, error) in [
+            ("*100000000000\r\n".to_string(), "too many arguments"),
+            (
+                "*1\r\n$18446744073709551615\r\n".to_string(),
+                "bulk string too long",
+            ),
+            // Never ends, inline or as a length.
+            ("a".repeat(MAX_LINE + 1), "line too long"),
+            (format!("*{}", "1".repeat(MAX_LINE)), "line too long"),
+        ] {
+            let mut c = connect();
+            c.writer.write_all(header.as_bytes()).unwrap();
+            assert_eq!(
+                c.read_reply(),
+                format!("-ERR Protocol error: {}\r\n", error)
+            );
+            let mut rest = String::new();
+            c.reader.read_to_string(&mut rest).unwrap();
+            assert_eq!(rest, "");
+        }
+
+        let mut c = connect();
+        assert_eq!(c.call(&["SET", "x", "1"]), "+OK\r\n");
+        assert_eq!(c.call(&["GET", "x"]), "$1\r\n1\r\n");
+    }
+}
@@ -0,0 +1,28 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+
+    #[test]
+    fn test_response_display() {
+        let db = Database::new();
+        let mut c = db.new_connection();
+
+        let commands = [
+            (Command::Begin, "[BEGIN] finish"),
+            (
+                Command::Set("x".to_string(), "hey".to_string()),
+                "[SET] key:x, val:hey",
+            ),
+            (Command::Get("x".to_string()), "[GET] key:x, val:hey"),
+            (Command::Delete("x".to_string()), "[DELETE] key:x"),
+            (Command::Get("y".to_string()), "[GET] key y not found"),
+            (Command::Commit, "[COMMIT] finish"),
+            (Command::Begin, "[BEGIN] finish"),
+            (Command::Abort, "[ABORT] finish"),
+        ];
+        for (command, expected) in commands {
+            assert_eq!(c.exec_command(command).unwrap().to_string(), expected);
+        }
+    }
+}
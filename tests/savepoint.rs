@@ -0,0 +1,184 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{get, setup};
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::collections::BTreeSet;
+    use std::fs;
+
+    fn set(c: &mut Connection, key: &str, val: &str) {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+    }
+
+    fn exec(c: &mut Connection, command: Command) -> String {
+        c.exec_command(command).unwrap().to_string()
+    }
+
+    fn keys(set: &BTreeSet<String>) -> Vec<&str> {
+        set.iter().map(|k| k.as_str()).collect()
+    }
+
+    #[test]
+    fn test_rollback_to_savepoint() {
+        let db = setup(IsolationLevel::Snapshot, &[("x", "0"), ("z", "0")]);
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        set(&mut c, "x", "1");
+        assert_eq!(
+            exec(&mut c, Command::Savepoint("a".to_string())),
+            "[SAVEPOINT] a"
+        );
+        set(&mut c, "x", "2");
+        set(&mut c, "x", "3");
+        set(&mut c, "y", "1");
+        c.exec_command(Command::Delete("z".to_string())).unwrap();
+        get(&mut c, "w");
+        assert_eq!(get(&mut c, "x"), Some("3".to_string()));
+
+        assert_eq!(
+            exec(&mut c, Command::RollbackTo("a".to_string())),
+            "[ROLLBACK TO] a"
+        );
+        assert_eq!(get(&mut c, "x"), Some("1".to_string()));
+        assert_eq!(get(&mut c, "y"), None);
+        assert_eq!(get(&mut c, "z"), Some("0".to_string()));
+        {
+            let tx = c.tx.as_ref().unwrap().read().unwrap();
+            assert_eq!(keys(&tx.write_set), ["x"]);
+            assert_eq!(keys(&tx.read_set), ["x", "y", "z"]);
+        }
+
+        // The savepoint is still there, and the rest stays committable.
+        set(&mut c, "x", "4");
+        c.exec_command(Command::RollbackTo("a".to_string()))
+            .unwrap();
+        c.exec_command(Command::Commit).unwrap();
+
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        assert_eq!(get(&mut c, "x"), Some("1".to_string()));
+        assert_eq!(get(&mut c, "y"), None);
+        assert_eq!(get(&mut c, "z"), Some("0".to_string()));
+        assert!(!db.kvs_info.read().unwrap().contains_key("y"));
+    }
+
+    #[test]
+    fn test_nested_savepoints_and_release() {
+        let db = setup(IsolationLevel::RepeatableRead, &[("x", "0"), ("z", "0")]);
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c.exec_command(Command::Savepoint("a".to_string())).unwrap();
+        set(&mut c, "x", "1");
+        c.exec_command(Command::Savepoint("b".to_string())).unwrap();
+        set(&mut c, "x", "2");
+        c.exec_command(Command::Savepoint("b".to_string())).unwrap();
+        set(&mut c, "x", "3");
+
+        // The newest savepoint wins when names repeat.
+        c.exec_command(Command::RollbackTo("b".to_string()))
+            .unwrap();
+        assert_eq!(get(&mut c, "x"), Some("2".to_string()));
+        assert_eq!(
+            exec(&mut c, Command::Release("b".to_string())),
+            "[RELEASE] b"
+        );
+        c.exec_command(Command::RollbackTo("b".to_string()))
+            .unwrap();
+        assert_eq!(get(&mut c, "x"), Some("1".to_string()));
+
+        // Releasing "a" also forgets "b", but keeps the writes.
+        c.exec_command(Command::Release("a".to_string())).unwrap();
+        assert_eq!(get(&mut c, "x"), Some("1".to_string()));
+        for name in ["a", "b"] {
+            assert_eq!(
+                c.exec_command(Command::RollbackTo(name.to_string())),
+                Err(Error::SavepointNotFound {
+                    tx_id: 2,
+                    name: name.to_string()
+                })
+            );
+        }
+        c.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_rollback_survives_vacuum() {
+        let db = setup(IsolationLevel::Snapshot, &[("x", "0"), ("z", "0")]);
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        set(&mut c, "x", "1");
+        c.exec_command(Command::Commit).unwrap();
+
+        // x has a dead version in front of the one about to be ended.
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c.exec_command(Command::Savepoint("a".to_string())).unwrap();
+        c.exec_command(Command::Delete("x".to_string())).unwrap();
+        assert_eq!(db.vacuum().versions_reclaimed, 1);
+        c.exec_command(Command::RollbackTo("a".to_string()))
+            .unwrap();
+        assert_eq!(get(&mut c, "x"), Some("1".to_string()));
+        c.exec_command(Command::Commit).unwrap();
+
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        assert_eq!(get(&mut c, "x"), Some("1".to_string()));
+    }
+
+    #[test]
+    fn test_rolled_back_reads_do_not_conflict() {
+        let db = setup(IsolationLevel::Serializable, &[("x", "0"), ("z", "0")]);
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+
+        get(&mut c1, "z");
+        c1.exec_command(Command::Savepoint("a".to_string()))
+            .unwrap();
+        get(&mut c1, "x");
+        c1.exec_command(Command::RollbackTo("a".to_string()))
+            .unwrap();
+        set(&mut c1, "z", "1");
+
+        // Without the read of x, there is no cycle.
+        get(&mut c2, "z");
+        set(&mut c2, "x", "1");
+        c2.exec_command(Command::Commit).unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_rollback_is_durable() {
+        let path =
+            std::env::temp_dir().join(format!("rrmvcc-savepoint-{}.wal", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let db = Database::open(&path).unwrap();
+            let mut c = db.new_connection();
+            c.exec_command(Command::Begin).unwrap();
+            set(&mut c, "x", "1");
+            c.exec_command(Command::Savepoint("a".to_string())).unwrap();
+            set(&mut c, "x", "2");
+            set(&mut c, "y", "2");
+            c.exec_command(Command::RollbackTo("a".to_string()))
+                .unwrap();
+            c.exec_command(Command::Release("a".to_string())).unwrap();
+            c.exec_command(Command::Commit).unwrap();
+        }
+
+        let mut db = Database::open(&path).unwrap();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        assert_eq!(get(&mut c, "x"), Some("1".to_string()));
+        assert_eq!(get(&mut c, "y"), None);
+        drop(c);
+        drop(db);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::scan::*;
+    use rrmvcc::tx::*;
+    use std::ops::Bound;
+
+    fn set(db: &Database, key: &str, val: &str) {
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+        c.exec_command(Command::Commit).unwrap();
+    }
+
+    fn rows(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn scan(c: &mut Connection, start: Bound<&str>, end: Bound<&str>) -> Vec<(String, String)> {
+        let command = Command::Scan {
+            start: start.map(str::to_string),
+            end: end.map(str::to_string),
+            limit: None,
+            reverse: false,
+        };
+        match c.exec_command(command) {
+            Ok(Response::Rows { rows, .. }) => rows,
+            ret => panic!("unexpected {:?}", ret),
+        }
+    }
+
+    #[test]
+    fn test_scan_bounds_limit_and_reverse() {
+        let db = Database::new();
+        for key in ["a", "b", "c", "d", "e"] {
+            set(&db, key, &key.to_uppercase());
+        }
+
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        assert_eq!(
+            scan(&mut c, Bound::Included("b"), Bound::Excluded("d")),
+            rows(&[("b", "B"), ("c", "C")])
+        );
+        assert_eq!(
+            scan(&mut c, Bound::Excluded("b"), Bound::Included("d")),
+            rows(&[("c", "C"), ("d", "D")])
+        );
+        assert_eq!(
+            scan(&mut c, Bound::Excluded("c"), Bound::Excluded("c")),
+            rows(&[])
+        );
+        assert_eq!(
+            scan(&mut c, Bound::Included("d"), Bound::Included("b")),
+            rows(&[])
+        );
+        assert_eq!(scan(&mut c, Bound::Unbounded, Bound::Unbounded).len(), 5);
+
+        let ret = c.exec_command(Command::Scan {
+            start: Bound::Unbounded,
+            end: Bound::Excluded("e".to_string()),
+            limit: Some(2),
+            reverse: true,
+        });
+        assert_eq!(
+            ret,
+            Ok(Response::Rows {
+                tx_id: 6,
+                rows: rows(&[("d", "D"), ("c", "C")])
+            })
+        );
+        assert_eq!(
+            ret.unwrap().to_string(),
+            "[SCAN] key:d, val:D\n[SCAN] key:c, val:C"
+        );
+    }
+
+    #[test]
+    fn test_scan_only_sees_visible_versions() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::RepeatableRead;
+        set(&db, "a", "1");
+        set(&db, "b", "1");
+        set(&db, "c", "1");
+
+        let mut reader = db.new_connection();
+        reader.exec_command(Command::Begin).unwrap();
+
+        let mut writer = db.new_connection();
+        writer.exec_command(Command::Begin).unwrap();
+        writer
+            .exec_command(Command::Set("a".to_string(), "2".to_string()))
+            .unwrap();
+        writer
+            .exec_command(Command::Delete("b".to_string()))
+            .unwrap();
+        writer
+            .exec_command(Command::Set("bb".to_string(), "2".to_string()))
+            .unwrap();
+
+        // The writer sees its own changes, the reader sees none of them, even
+        // once they are committed.
+        assert_eq!(
+            scan(&mut writer, Bound::Unbounded, Bound::Unbounded),
+            rows(&[("a", "2"), ("bb", "2"), ("c", "1")])
+        );
+        assert_eq!(
+            scan(&mut reader, Bound::Unbounded, Bound::Unbounded),
+            rows(&[("a", "1"), ("b", "1"), ("c", "1")])
+        );
+        writer.exec_command(Command::Commit).unwrap();
+        assert_eq!(
+            scan(&mut reader, Bound::Unbounded, Bound::Unbounded),
+            rows(&[("a", "1"), ("b", "1"), ("c", "1")])
+        );
+
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        assert_eq!(
+            scan(&mut c, Bound::Unbounded, Bound::Unbounded),
+            rows(&[("a", "2"), ("bb", "2"), ("c", "1")])
+        );
+    }
+
+    #[test]
+    fn test_scan_prefix() {
+        let db = Database::new();
+        for key in [
+            "ap",
+            "app",
+            "apple",
+            "apricot",
+            "b",
+            "\u{10FFFF}",
+            "\u{10FFFF}x",
+        ] {
+            set(&db, key, "v");
+        }
+
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        let keys = |c: &mut Connection, prefix: &str, reverse: bool| -> Vec<String> {
+            match c.exec_command(Command::ScanPrefix {
+                prefix: prefix.to_string(),
+                limit: None,
+                reverse,
+            }) {
+                Ok(Response::Rows { rows, .. }) => rows.into_iter().map(|(k, _)| k).collect(),
+                ret => panic!("unexpected {:?}", ret),
+            }
+        };
+        assert_eq!(keys(&mut c, "app", false), ["app", "apple"]);
+        assert_eq!(keys(&mut c, "ap", true), ["apricot", "apple", "app", "ap"]);
+        assert_eq!(
+            keys(&mut c, "\u{10FFFF}", false),
+            ["\u{10FFFF}", "\u{10FFFF}x"]
+        );
+        assert_eq!(keys(&mut c, "c", false), Vec::<String>::new());
+
+        assert_eq!(
+            vec![1u8, 255].prefix_range(),
+            (Bound::Included(vec![1, 255]), Bound::Excluded(vec![2]))
+        );
+        assert_eq!(
+            (1u64, 2u32).prefix_range(),
+            (Bound::Included((1, 2)), Bound::Included((1, 2)))
+        );
+    }
+
+    #[test]
+    fn test_scan_streams_without_holding_locks() {
+        let db = Database::new();
+        for i in 0..10 {
+            set(&db, &format!("k{}", i), "old");
+        }
+
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        let mut scan = c.scan(Bound::Unbounded, Bound::Unbounded, false).unwrap();
+        assert_eq!(scan.next(), Some(("k0".to_string(), "old".to_string())));
+
+        // Writers are not blocked by the open scan.
+        set(&db, "k5", "new");
+        let rest: Vec<_> = scan.collect();
+        assert_eq!(rest.len(), 9);
+        assert_eq!(rest[4], ("k5".to_string(), "new".to_string()));
+        assert_eq!(c.tx.unwrap().read().unwrap().read_set.len(), 10);
+    }
+}
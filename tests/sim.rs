@@ -0,0 +1,120 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common;
+    use rrmvcc::db::*;
+    use rrmvcc::sim::*;
+    use rrmvcc::tx::*;
+
+    fn get(key: &str) -> Command {
+        Command::Get(key.to_string())
+    }
+
+    fn set(key: &str, val: &str) -> Command {
+        Command::Set(key.to_string(), val.to_string())
+    }
+
+    fn step(connection: usize, command: Command) -> Step {
+        Step {
+            connection,
+            command,
+        }
+    }
+
+    fn increments() -> Vec<Vec<Command>> {
+        vec![
+            vec![Command::Begin, get("x"), set("x", "2"), Command::Commit],
+            vec![Command::Begin, get("x"), set("x", "3"), Command::Commit],
+        ]
+    }
+
+    fn setup(level: IsolationLevel) -> impl Fn() -> Database {
+        move || common::setup(level.clone(), &[("x", "1"), ("y", "1")])
+    }
+
+    #[test]
+    fn test_schedules() {
+        let sim = Simulation::new(vec![
+            vec![Command::Begin, Command::Commit],
+            vec![Command::Begin],
+        ]);
+        let schedules: Vec<_> = sim.schedules().collect();
+        assert_eq!(schedules, [[0, 0, 1], [0, 1, 0], [1, 0, 0]]);
+        assert_eq!(
+            sim.interleave(&[0, 1, 0]),
+            [
+                step(0, Command::Begin),
+                step(1, Command::Begin),
+                step(0, Command::Commit)
+            ]
+        );
+
+        let schedule = sim.random_schedule(42);
+        assert_eq!(sim.random_schedule(42), schedule);
+        let mut sorted = schedule.clone();
+        sorted.sort();
+        assert_eq!(sorted, [0, 0, 1]);
+    }
+
+    #[test]
+    fn test_snapshot_passes_every_interleaving() {
+        let sim = Simulation::new(increments()).setup(setup(IsolationLevel::Snapshot));
+        assert_eq!(sim.check_all(serializable).unwrap(), 70);
+    }
+
+    #[test]
+    fn test_shrinks_lost_update() {
+        let sim = Simulation::new(increments()).setup(setup(IsolationLevel::ReadCommitted));
+        let failure = sim.check_all(serializable).unwrap_err();
+        assert_eq!(failure.seed, None);
+        assert_eq!(failure.message, "G2-item on transactions [3, 2]");
+        assert_eq!(
+            failure.steps,
+            [
+                step(0, Command::Begin),
+                step(0, set("x", "2")),
+                step(1, Command::Begin),
+                step(1, get("x")),
+                step(0, Command::Commit),
+                step(1, set("x", "3")),
+                step(1, Command::Commit),
+            ]
+        );
+        assert!(failure.to_string().ends_with(
+            "c1: Set(\"x\", \"3\") => [SET] key:x, val:3\nc1: Commit => [COMMIT] finish"
+        ));
+    }
+
+    #[test]
+    fn test_random_runs_are_reproducible() {
+        // Write skew: each reads both keys and writes one of them.
+        let scripts = vec![
+            vec![
+                Command::Begin,
+                get("x"),
+                get("y"),
+                set("x", "0"),
+                Command::Commit,
+            ],
+            vec![
+                Command::Begin,
+                get("x"),
+                get("y"),
+                set("y", "0"),
+                Command::Commit,
+            ],
+        ];
+
+        let sim = Simulation::new(scripts.clone()).setup(setup(IsolationLevel::Serializable));
+        sim.check_random(7, 50, serializable).unwrap();
+
+        let sim = Simulation::new(scripts).setup(setup(IsolationLevel::Snapshot));
+        let failure = sim.check_random(7, 50, serializable).unwrap_err();
+        assert!(failure.message.starts_with("G2-item"));
+        assert_eq!(failure.steps.len(), 8);
+        let seed = failure.seed.unwrap();
+        let run = sim.run(&sim.interleave(&sim.random_schedule(seed)));
+        assert_eq!(serializable(&run).unwrap_err(), failure.message);
+    }
+}
@@ -2,6 +2,7 @@
 mod tests {
     use rrmvcc::db::*;
     use rrmvcc::tx::*;
+    use rrmvcc::Error;
 
     #[test]
     fn test_snapshot() {
@@ -17,24 +18,44 @@ mod tests {
         let mut c3 = db.new_connection();
         c3.exec_command(Command::Begin).unwrap();
 
-        if let Ok(ret) = c1.exec_command(Command::Set("x".to_string(), "hey".to_string())) {
-            assert_eq!(ret, "[SET] key:x, val:hey");
-        }
-
-        if let Ok(ret) = c1.exec_command(Command::Commit) {
-            assert_eq!(ret, "[COMMIT] finish");
-        }
-
-        if let Ok(ret) = c2.exec_command(Command::Set("x".to_string(), "hey".to_string())) {
-            assert_eq!(ret, "[SET] key:x, val:hey");
-        }
-
-        if let Err(ret) = c2.exec_command(Command::Commit) {
-            assert_eq!(ret, "Write-Write Conflict");
-        }
-
-        if let Ok(ret) = c3.exec_command(Command::Set("y".to_string(), "no conflict".to_string())){
-            assert_eq!(ret, "[SET] key:y, val:no conflict");
-        }
+        assert_eq!(
+            c1.exec_command(Command::Set("x".to_string(), "hey".to_string())),
+            Ok(Response::Written {
+                tx_id: 1,
+                key: "x".to_string(),
+                value: "hey".to_string()
+            })
+        );
+
+        assert_eq!(
+            c1.exec_command(Command::Commit),
+            Ok(Response::Committed { tx_id: 1 })
+        );
+
+        assert_eq!(
+            c2.exec_command(Command::Set("x".to_string(), "hey".to_string())),
+            Ok(Response::Written {
+                tx_id: 2,
+                key: "x".to_string(),
+                value: "hey".to_string()
+            })
+        );
+
+        assert_eq!(
+            c2.exec_command(Command::Commit),
+            Err(Error::WriteWriteConflict {
+                key: "x".to_string(),
+                other_tx: 1
+            })
+        );
+
+        assert_eq!(
+            c3.exec_command(Command::Set("y".to_string(), "no conflict".to_string())),
+            Ok(Response::Written {
+                tx_id: 3,
+                key: "y".to_string(),
+                value: "no conflict".to_string()
+            })
+        );
     }
 }
@@ -0,0 +1,230 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{get, setup};
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+
+    fn set(c: &mut Connection, key: &str, val: &str) {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+    }
+
+    fn begin(db: &Database) -> Connection {
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c
+    }
+
+    #[test]
+    fn test_write_skew_is_caught() {
+        // Both doctors check that someone else is on call, then leave.
+        let db = setup(
+            IsolationLevel::Serializable,
+            &[("alice", "on"), ("bob", "on")],
+        );
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+
+        assert_eq!(get(&mut c1, "alice"), Some("on".to_string()));
+        assert_eq!(get(&mut c1, "bob"), Some("on".to_string()));
+        assert_eq!(get(&mut c2, "alice"), Some("on".to_string()));
+        assert_eq!(get(&mut c2, "bob"), Some("on".to_string()));
+        set(&mut c1, "alice", "off");
+        set(&mut c2, "bob", "off");
+
+        c1.exec_command(Command::Commit).unwrap();
+        let err = c2.exec_command(Command::Commit).unwrap_err();
+        assert_eq!(
+            err,
+            Error::ReadWriteConflict {
+                key: "alice".to_string(),
+                other_tx: 2
+            }
+        );
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn test_pivot_aborts_before_its_reader_commits() {
+        // c3 -rw-> c2 -rw-> c1, where c1 commits first and c3 is still
+        // running when the pivot c2 tries to commit.
+        let db = setup(
+            IsolationLevel::Serializable,
+            &[("x", "0"), ("y", "0"), ("z", "0")],
+        );
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        let mut c3 = begin(&db);
+
+        get(&mut c2, "x");
+        set(&mut c1, "x", "1");
+        c1.exec_command(Command::Commit).unwrap();
+        get(&mut c3, "y");
+        set(&mut c3, "z", "3");
+        set(&mut c2, "y", "2");
+        assert_eq!(
+            c2.exec_command(Command::Commit),
+            Err(Error::ReadWriteConflict {
+                key: "x".to_string(),
+                other_tx: 2
+            })
+        );
+        c3.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_reader_aborts_after_pivot_committed() {
+        // Same structure, but the pivot commits before its reader does.
+        let db = setup(IsolationLevel::Serializable, &[("x", "0"), ("y", "0")]);
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        let mut c3 = begin(&db);
+
+        get(&mut c2, "x");
+        set(&mut c1, "x", "1");
+        c1.exec_command(Command::Commit).unwrap();
+        set(&mut c2, "y", "2");
+        c2.exec_command(Command::Commit).unwrap();
+        get(&mut c3, "y");
+        set(&mut c3, "z", "3");
+        assert_eq!(
+            c3.exec_command(Command::Commit),
+            Err(Error::ReadWriteConflict {
+                key: "y".to_string(),
+                other_tx: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_structure_is_safe_unless_out_commits_first() {
+        // c3 -rw-> c2 -rw-> c1, but c3 commits before c1 does, so the
+        // history is equivalent to c3, c2, c1.
+        let db = setup(IsolationLevel::Serializable, &[("x", "0"), ("y", "0")]);
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        let mut c3 = begin(&db);
+
+        get(&mut c3, "y");
+        set(&mut c3, "z", "3");
+        get(&mut c2, "x");
+        set(&mut c2, "y", "2");
+        c3.exec_command(Command::Commit).unwrap();
+        set(&mut c1, "x", "1");
+        c1.exec_command(Command::Commit).unwrap();
+        c2.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_read_only_anomaly() {
+        // Fekete, O'Neil and O'Neil: the read-only c3 sees the deposit but
+        // not the withdrawal that was decided before it.
+        let db = setup(
+            IsolationLevel::Serializable,
+            &[("checking", "0"), ("savings", "0")],
+        );
+        let mut withdraw = begin(&db);
+        let mut deposit = begin(&db);
+
+        get(&mut withdraw, "checking");
+        get(&mut withdraw, "savings");
+        get(&mut deposit, "savings");
+        set(&mut deposit, "savings", "20");
+        deposit.exec_command(Command::Commit).unwrap();
+
+        let mut report = begin(&db);
+        assert_eq!(get(&mut report, "checking"), Some("0".to_string()));
+        assert_eq!(get(&mut report, "savings"), Some("20".to_string()));
+        report.exec_command(Command::Commit).unwrap();
+
+        set(&mut withdraw, "checking", "-11");
+        assert_eq!(
+            withdraw.exec_command(Command::Commit),
+            Err(Error::ReadWriteConflict {
+                key: "savings".to_string(),
+                other_tx: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_only_transaction_before_the_out_edge_commits() {
+        // Same as the read-only anomaly, but the report starts before the
+        // deposit commits, so it can serialize before everything.
+        let db = setup(
+            IsolationLevel::Serializable,
+            &[("checking", "0"), ("savings", "0")],
+        );
+        let mut withdraw = begin(&db);
+        let mut deposit = begin(&db);
+        let mut report = begin(&db);
+
+        get(&mut withdraw, "checking");
+        get(&mut withdraw, "savings");
+        get(&mut deposit, "savings");
+        set(&mut deposit, "savings", "20");
+        deposit.exec_command(Command::Commit).unwrap();
+
+        assert_eq!(get(&mut report, "checking"), Some("0".to_string()));
+        assert_eq!(get(&mut report, "savings"), Some("0".to_string()));
+        report.exec_command(Command::Commit).unwrap();
+
+        set(&mut withdraw, "checking", "-11");
+        withdraw.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_benign_interleavings_commit() {
+        let db = setup(IsolationLevel::Serializable, &[("x", "0"), ("y", "0")]);
+
+        // A reader of a key overwritten concurrently serializes first.
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        get(&mut c1, "x");
+        set(&mut c2, "x", "1");
+        c2.exec_command(Command::Commit).unwrap();
+        set(&mut c1, "y", "1");
+        c1.exec_command(Command::Commit).unwrap();
+
+        // A chain without a cycle: c3 -rw-> c4 -rw-> c5, where c5 commits
+        // last.
+        let mut c3 = begin(&db);
+        let mut c4 = begin(&db);
+        let mut c5 = begin(&db);
+        get(&mut c3, "x");
+        get(&mut c4, "y");
+        set(&mut c4, "x", "2");
+        c4.exec_command(Command::Commit).unwrap();
+        c3.exec_command(Command::Commit).unwrap();
+        set(&mut c5, "y", "2");
+        c5.exec_command(Command::Commit).unwrap();
+
+        // Blind writes to different keys never conflict.
+        let mut c6 = begin(&db);
+        let mut c7 = begin(&db);
+        set(&mut c6, "x", "3");
+        set(&mut c7, "y", "3");
+        c6.exec_command(Command::Commit).unwrap();
+        c7.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_write_write_conflicts_still_abort() {
+        let db = setup(IsolationLevel::Serializable, &[("x", "0")]);
+        let mut c1 = begin(&db);
+        let mut c2 = begin(&db);
+        set(&mut c1, "x", "1");
+        set(&mut c2, "x", "2");
+        c1.exec_command(Command::Commit).unwrap();
+        assert_eq!(
+            c2.exec_command(Command::Commit),
+            Err(Error::WriteWriteConflict {
+                key: "x".to_string(),
+                other_tx: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::stats::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::Error;
+    use std::collections::BTreeMap;
+
+    fn set(c: &mut Connection, key: &str, val: &str) -> Result<Response, Error> {
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+    }
+
+    fn begin(db: &Database, isolation: IsolationLevel) -> Connection {
+        let mut c = db.new_connection();
+        c.exec_command(Command::BeginWith(BeginOptions {
+            isolation: Some(isolation),
+            ..Default::default()
+        }))
+        .unwrap();
+        c
+    }
+
+    #[test]
+    fn test_transaction_counters() {
+        let db = Database::new();
+        let mut c = begin(&db, IsolationLevel::ReadCommitted);
+        set(&mut c, "x", "1").unwrap();
+        c.exec_command(Command::Commit).unwrap();
+        let mut c = begin(&db, IsolationLevel::ReadCommitted);
+        c.exec_command(Command::Abort).unwrap();
+
+        // The second committer loses.
+        let mut c1 = begin(&db, IsolationLevel::Snapshot);
+        let mut c2 = begin(&db, IsolationLevel::Snapshot);
+        set(&mut c1, "x", "2").unwrap();
+        set(&mut c2, "x", "3").unwrap();
+        c1.exec_command(Command::Commit).unwrap();
+        assert!(c2.exec_command(Command::Commit).is_err());
+        let _running = begin(&db, IsolationLevel::Snapshot);
+
+        let stats = db.stats();
+        assert_eq!(
+            stats.transactions,
+            TxCounts {
+                begun: 5,
+                committed: 2,
+                aborted: 2
+            }
+        );
+        assert_eq!(
+            stats.by_isolation,
+            BTreeMap::from([
+                (
+                    IsolationLevel::ReadCommitted,
+                    TxCounts {
+                        begun: 2,
+                        committed: 1,
+                        aborted: 1
+                    }
+                ),
+                (
+                    IsolationLevel::Snapshot,
+                    TxCounts {
+                        begun: 3,
+                        committed: 1,
+                        aborted: 1
+                    }
+                ),
+            ])
+        );
+        assert_eq!(
+            stats.aborts,
+            BTreeMap::from([(AbortReason::User, 1), (AbortReason::WriteWriteConflict, 1)])
+        );
+        assert_eq!(stats.active_transactions, 1);
+    }
+
+    #[test]
+    fn test_version_counts() {
+        let db = Database::new();
+        assert_eq!(db.stats().avg_chain_length, 0.0);
+
+        let mut c = begin(&db, IsolationLevel::Snapshot);
+        set(&mut c, "x", "1").unwrap();
+        set(&mut c, "y", "1").unwrap();
+        c.exec_command(Command::Commit).unwrap();
+        let mut c = begin(&db, IsolationLevel::Snapshot);
+        set(&mut c, "x", "2").unwrap();
+        c.exec_command(Command::Delete("y".to_string())).unwrap();
+        c.exec_command(Command::Commit).unwrap();
+        let mut c = begin(&db, IsolationLevel::Snapshot);
+        set(&mut c, "z", "1").unwrap();
+        c.exec_command(Command::Abort).unwrap();
+
+        // x1 and y1 were ended by a commit, z1 was written by an abort.
+        let stats = db.stats();
+        assert_eq!(stats.live_keys, 1);
+        assert_eq!(stats.versions, 4);
+        assert_eq!(stats.dead_versions, 3);
+        assert_eq!(stats.avg_chain_length, 4.0 / 3.0);
+
+        db.vacuum();
+        let stats = db.stats();
+        assert_eq!(
+            (stats.live_keys, stats.versions, stats.dead_versions),
+            (1, 1, 0)
+        );
+        assert_eq!(stats.avg_chain_length, 1.0);
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn test_prometheus() {
+        let db = Database::new();
+        let mut c = begin(&db, IsolationLevel::Serializable);
+        set(&mut c, "x", "1").unwrap();
+        c.exec_command(Command::Commit).unwrap();
+        let mut c = begin(&db, IsolationLevel::Serializable);
+        c.exec_command(Command::Abort).unwrap();
+
+        let text = db.stats().to_prometheus();
+        for line in [
+            "# TYPE rrmvcc_transactions_begun_total counter",
+            "rrmvcc_transactions_begun_total{isolation=\"serializable\"} 2",
+            "rrmvcc_transactions_committed_total{isolation=\"serializable\"} 1",
+            "rrmvcc_transaction_aborts_total{reason=\"user\"} 1",
+            "# TYPE rrmvcc_versions gauge",
+            "rrmvcc_live_keys 1",
+            "rrmvcc_avg_chain_length 1",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "{} missing from\n{}",
+                line,
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,134 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::get;
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::vacuum::*;
+    use std::time::Duration;
+
+    fn versions(db: &Database, key: &str) -> usize {
+        db.kvs_info
+            .read()
+            .unwrap()
+            .get(key)
+            .map_or(0, |values| values.len())
+    }
+
+    fn set(db: &Database, key: &str, val: &str) {
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        c.exec_command(Command::Set(key.to_string(), val.to_string()))
+            .unwrap();
+        c.exec_command(Command::Commit).unwrap();
+    }
+
+    #[test]
+    fn test_vacuum_keeps_versions_visible_to_active_snapshots() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::RepeatableRead;
+
+        set(&db, "x", "v1");
+        let mut reader = db.new_connection();
+        reader.exec_command(Command::Begin).unwrap();
+        set(&db, "x", "v2");
+        set(&db, "x", "v3");
+        assert_eq!(versions(&db, "x"), 3);
+
+        // v2 is dead already, v1 is still what the reader sees.
+        let stats = db.vacuum();
+        assert_eq!(stats.versions_reclaimed, 1);
+        assert_eq!(versions(&db, "x"), 2);
+        assert_eq!(get(&mut reader, "x"), Some("v1".to_string()));
+        reader.exec_command(Command::Commit).unwrap();
+
+        let stats = db.vacuum();
+        assert_eq!(stats.versions_reclaimed, 1);
+        assert_eq!(versions(&db, "x"), 1);
+
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        assert_eq!(get(&mut c, "x"), Some("v3".to_string()));
+    }
+
+    #[test]
+    fn test_vacuum_reclaims_aborted_writes_and_deleted_keys() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+
+        set(&db, "x", "hey");
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        c1.exec_command(Command::Set("x".to_string(), "aborted".to_string()))
+            .unwrap();
+        c1.exec_command(Command::Set("y".to_string(), "aborted".to_string()))
+            .unwrap();
+        c1.exec_command(Command::Abort).unwrap();
+
+        let mut c2 = db.new_connection();
+        c2.exec_command(Command::Begin).unwrap();
+        c2.exec_command(Command::Delete("x".to_string())).unwrap();
+        c2.exec_command(Command::Commit).unwrap();
+
+        let stats = db.vacuum();
+        assert_eq!(
+            stats,
+            VacuumStats {
+                versions_reclaimed: 3,
+                transactions_reclaimed: 3,
+            }
+        );
+        assert!(db.kvs_info.read().unwrap().is_empty());
+        assert!(db.txs_info.read().unwrap().txs.is_empty());
+        assert_eq!(db.vacuum(), VacuumStats::default());
+    }
+
+    #[test]
+    fn test_vacuum_reset_aborted_end_stamps() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+
+        set(&db, "x", "hey");
+        let mut c1 = db.new_connection();
+        c1.exec_command(Command::Begin).unwrap();
+        c1.exec_command(Command::Delete("x".to_string())).unwrap();
+        c1.exec_command(Command::Abort).unwrap();
+
+        let stats = db.vacuum();
+        assert_eq!(stats.versions_reclaimed, 0);
+        assert_eq!(stats.transactions_reclaimed, 2);
+        assert_eq!(db.kvs_info.read().unwrap()["x"][0].tx_end_id, 0);
+
+        for level in [
+            IsolationLevel::ReadCommitted,
+            IsolationLevel::RepeatableRead,
+            IsolationLevel::Snapshot,
+            IsolationLevel::Serializable,
+        ] {
+            let mut db = db.clone();
+            db.default_isolation_level = level;
+            let mut c = db.new_connection();
+            c.exec_command(Command::Begin).unwrap();
+            assert_eq!(get(&mut c, "x"), Some("hey".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_background_vacuum() {
+        let mut db = Database::new();
+        db.default_isolation_level = IsolationLevel::Snapshot;
+
+        let handle = db.spawn_vacuum(Duration::from_millis(5));
+        for i in 0..20 {
+            set(&db, "x", &i.to_string());
+        }
+        while handle.stats().versions_reclaimed < 19 {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let stats = handle.stop();
+        assert_eq!(stats.versions_reclaimed, 19);
+        assert_eq!(stats.transactions_reclaimed, 20);
+        assert_eq!(versions(&db, "x"), 1);
+    }
+}
@@ -0,0 +1,112 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{get, setup};
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+
+    #[test]
+    fn test_read_committed_visibility() {
+        let mut db: Database = Database::new();
+        db.default_isolation_level = IsolationLevel::ReadCommitted;
+        let writer = db.new_transaction();
+        let ender = db.new_transaction();
+        let reader = db.new_transaction();
+        let writer_id = writer.read().unwrap().id;
+        let ender_id = ender.read().unwrap().id;
+        let mut version = Value {
+            data: "x".to_string(),
+            tx_start_id: writer_id,
+            tx_end_id: 0,
+        };
+
+        // Written by a transaction that has not committed yet, then has.
+        assert!(!db.is_visible(&reader, &version));
+        db.complete_transaction(writer_id, TransactionState::Committed)
+            .unwrap();
+        assert!(db.is_visible(&reader, &version));
+
+        // Ended by a transaction that has not committed yet, then has.
+        version.tx_end_id = ender_id;
+        assert!(db.is_visible(&reader, &version));
+        db.complete_transaction(ender_id, TransactionState::Committed)
+            .unwrap();
+        assert!(!db.is_visible(&reader, &version));
+    }
+
+    /// A write takes over the end stamp of a version another transaction
+    /// deleted, so the delete has to be stamped again when its transaction
+    /// commits.
+    #[test]
+    fn test_committed_delete_survives_a_concurrent_write() {
+        let levels = [
+            IsolationLevel::ReadCommitted,
+            IsolationLevel::RepeatableRead,
+            IsolationLevel::Snapshot,
+            IsolationLevel::Serializable,
+        ];
+        for level in levels {
+            // Whether the writer aborts before the delete commits, and whether
+            // vacuum clears the end stamp it left in between.
+            for (abort_first, vacuum) in [(false, false), (true, false), (true, true)] {
+                let db = setup(level.clone(), &[("x", "hey")]);
+                let mut deleter = db.new_connection();
+                let mut writer = db.new_connection();
+                deleter.exec_command(Command::Begin).unwrap();
+                writer.exec_command(Command::Begin).unwrap();
+                deleter
+                    .exec_command(Command::Delete("x".to_string()))
+                    .unwrap();
+                writer
+                    .exec_command(Command::Set("x".to_string(), "yall".to_string()))
+                    .unwrap();
+                if abort_first {
+                    writer.exec_command(Command::Abort).unwrap();
+                    if vacuum {
+                        db.vacuum();
+                    }
+                }
+                deleter.exec_command(Command::Commit).unwrap();
+                if !abort_first {
+                    writer.exec_command(Command::Abort).unwrap();
+                }
+
+                let mut reader = db.new_connection();
+                reader.exec_command(Command::Begin).unwrap();
+                assert_eq!(
+                    get(&mut reader, "x"),
+                    None,
+                    "{:?}, abort first: {}, vacuum: {}",
+                    level,
+                    abort_first,
+                    vacuum
+                );
+            }
+
+            // The writer's snapshot still has the version once the delete
+            // committed. It reads its own write and leaves the committed
+            // delete alone.
+            let db = setup(level.clone(), &[("x", "hey")]);
+            let mut deleter = db.new_connection();
+            let mut writer = db.new_connection();
+            writer.exec_command(Command::Begin).unwrap();
+            deleter.exec_command(Command::Begin).unwrap();
+            deleter
+                .exec_command(Command::Delete("x".to_string()))
+                .unwrap();
+            deleter.exec_command(Command::Commit).unwrap();
+            let snapshot = level != IsolationLevel::ReadCommitted;
+            assert_eq!(get(&mut writer, "x").is_some(), snapshot, "{:?}", level);
+            writer
+                .exec_command(Command::Set("x".to_string(), "yall".to_string()))
+                .unwrap();
+            assert_eq!(get(&mut writer, "x"), Some("yall".to_string()));
+            writer.exec_command(Command::Abort).unwrap();
+
+            let mut reader = db.new_connection();
+            reader.exec_command(Command::Begin).unwrap();
+            assert_eq!(get(&mut reader, "x"), None, "{:?}", level);
+        }
+    }
+}
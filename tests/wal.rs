@@ -0,0 +1,202 @@
+#[cfg(test)]
+mod tests {
+    use rrmvcc::db::*;
+    use rrmvcc::tx::*;
+    use rrmvcc::wal::*;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn wal_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rrmvcc-{}-{}.wal", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn read_all(db: &Database, keys: &[&str]) -> BTreeMap<String, String> {
+        let mut db = db.clone();
+        db.default_isolation_level = IsolationLevel::RepeatableRead;
+        let mut c = db.new_connection();
+        c.exec_command(Command::Begin).unwrap();
+        let mut state = BTreeMap::new();
+        for key in keys {
+            match c.exec_command(Command::Get(key.to_string())).unwrap() {
+                Response::Value {
+                    value: Some(value), ..
+                } => {
+                    state.insert(key.to_string(), value);
+                }
+                Response::Value { value: None, .. } => {}
+                ret => panic!("unexpected {:?}", ret),
+            }
+        }
+        c.exec_command(Command::Commit).unwrap();
+        state
+    }
+
+    #[test]
+    fn test_recover_committed_transactions() {
+        let path = wal_path("recover");
+        {
+            let mut db = Database::open(&path).unwrap();
+            db.default_isolation_level = IsolationLevel::Snapshot;
+
+            let mut c1 = db.new_connection();
+            c1.exec_command(Command::Begin).unwrap();
+            c1.exec_command(Command::Set("x".to_string(), "hey".to_string()))
+                .unwrap();
+            c1.exec_command(Command::Set("y".to_string(), "yall".to_string()))
+                .unwrap();
+            c1.exec_command(Command::Commit).unwrap();
+
+            let mut c2 = db.new_connection();
+            c2.exec_command(Command::Begin).unwrap();
+            c2.exec_command(Command::Delete("y".to_string())).unwrap();
+            c2.exec_command(Command::Abort).unwrap();
+
+            let mut c3 = db.new_connection();
+            c3.exec_command(Command::Begin).unwrap();
+            c3.exec_command(Command::Delete("x".to_string())).unwrap();
+            c3.exec_command(Command::Commit).unwrap();
+
+            // Still active when the process goes away.
+            let mut c4 = db.new_connection();
+            c4.exec_command(Command::Begin).unwrap();
+            c4.exec_command(Command::Set("z".to_string(), "lost".to_string()))
+                .unwrap();
+        }
+
+        let db = Database::open(&path).unwrap();
+        let state = read_all(&db, &["x", "y", "z"]);
+        assert_eq!(
+            state,
+            BTreeMap::from([("y".to_string(), "yall".to_string())])
+        );
+
+        let txs_info = db.txs_info.read().unwrap();
+        let states: Vec<_> = (1..=4)
+            .map(|tx_id| txs_info.get_transaction_state(tx_id).unwrap())
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                TransactionState::Committed,
+                TransactionState::Aborted,
+                TransactionState::Committed,
+                TransactionState::Aborted,
+            ]
+        );
+        drop(txs_info);
+        drop(db);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sync_policies() {
+        for (name, policy) in [
+            ("sync-always", SyncPolicy::Always),
+            ("sync-every", SyncPolicy::Every(3)),
+            ("sync-never", SyncPolicy::Never),
+        ] {
+            let path = wal_path(name);
+            {
+                let db = Database::open_with(&path, policy).unwrap();
+                let mut c = db.new_connection();
+                for i in 0..10 {
+                    c.exec_command(Command::Begin).unwrap();
+                    c.exec_command(Command::Set(format!("k{}", i), i.to_string()))
+                        .unwrap();
+                    c.exec_command(Command::Commit).unwrap();
+                }
+            }
+
+            let db = Database::open_with(&path, policy).unwrap();
+            let keys: Vec<String> = (0..10).map(|i| format!("k{}", i)).collect();
+            let keys: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+            assert_eq!(read_all(&db, &keys).len(), 10);
+            drop(db);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_crash_at_arbitrary_offsets() {
+        let keys = ["a", "b", "c", "d"];
+        let path = wal_path("crash-source");
+
+        // (log length, committed state) after every successful commit.
+        let mut checkpoints = vec![(0u64, BTreeMap::new())];
+        {
+            let mut db = Database::open_with(&path, SyncPolicy::Never).unwrap();
+            db.default_isolation_level = IsolationLevel::Snapshot;
+            let mut committed: BTreeMap<String, String> = BTreeMap::new();
+
+            for round in 0..6 {
+                let mut c1 = db.new_connection();
+                let mut c2 = db.new_connection();
+                c1.exec_command(Command::Begin).unwrap();
+                c2.exec_command(Command::Begin).unwrap();
+
+                let (k1, k2) = (keys[round % 4], keys[(round + 1) % 4]);
+                let v = format!("v{}", round);
+                c1.exec_command(Command::Set(k1.to_string(), v.clone()))
+                    .unwrap();
+                c2.exec_command(Command::Set(k2.to_string(), v.clone()))
+                    .unwrap();
+                if round % 3 == 2 && committed.contains_key(keys[(round + 2) % 4]) {
+                    c1.exec_command(Command::Delete(keys[(round + 2) % 4].to_string()))
+                        .unwrap();
+                }
+
+                c1.exec_command(Command::Commit).unwrap();
+                committed.insert(k1.to_string(), v.clone());
+                if round % 3 == 2 {
+                    committed.remove(keys[(round + 2) % 4]);
+                }
+                checkpoints.push((fs::metadata(&path).unwrap().len(), committed.clone()));
+
+                if round % 2 == 0 {
+                    c2.exec_command(Command::Abort).unwrap();
+                } else {
+                    c2.exec_command(Command::Commit).unwrap();
+                    committed.insert(k2.to_string(), v.clone());
+                    checkpoints.push((fs::metadata(&path).unwrap().len(), committed.clone()));
+                }
+            }
+        }
+
+        let log = fs::read(&path).unwrap();
+        let crashed = wal_path("crash-truncated");
+        for offset in 0..=log.len() {
+            fs::write(&crashed, &log[..offset]).unwrap();
+            let expected = &checkpoints
+                .iter()
+                .rev()
+                .find(|(len, _)| *len as usize <= offset)
+                .unwrap()
+                .1;
+
+            {
+                let db = Database::open_with(&crashed, SyncPolicy::Never).unwrap();
+                assert_eq!(&read_all(&db, &keys), expected, "offset {}", offset);
+
+                // The torn tail must not get in the way of new writes.
+                let mut c = db.new_connection();
+                c.exec_command(Command::Begin).unwrap();
+                c.exec_command(Command::Set("after".to_string(), "crash".to_string()))
+                    .unwrap();
+                c.exec_command(Command::Commit).unwrap();
+            }
+
+            let db = Database::open_with(&crashed, SyncPolicy::Never).unwrap();
+            let mut expected = expected.clone();
+            expected.insert("after".to_string(), "crash".to_string());
+            let mut all_keys = keys.to_vec();
+            all_keys.push("after");
+            assert_eq!(read_all(&db, &all_keys), expected, "offset {}", offset);
+        }
+
+        fs::remove_file(&crashed).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}